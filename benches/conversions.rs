@@ -0,0 +1,70 @@
+//! Batch conversions against cached rates, run with `cargo bench`.
+use std::time::Instant;
+
+use currency_exchange::providers::{self, Provider};
+use currency_exchange::{cache, config, convert_value, requests};
+
+const CONVERSIONS: u32 = 2000;
+const CODES: [&str; 5] = ["EUR", "USD", "GBP", "CHF", "JPY"];
+
+fn setup() {
+    let path = std::env::temp_dir().join("benchCurrencyCache.db");
+    std::env::set_var(config::CACHE_LOCATION_ENV_NAME, &path);
+    std::env::set_var(config::PROVIDER_ENV_NAME, providers::mock::ID);
+    cache::create_cache().expect("Error creating bench cache");
+    requests::get_currencies().expect("Error getting currencies");
+    // Mock provider only lists few currencies, but has rates of more of them
+    for code in CODES {
+        cache::add_code([code.to_string(), String::new()]).expect("Error adding code");
+    }
+    requests::get_rates("PLN").expect("Error getting rates");
+    // EUR rates as if fetched from default provider, which is set up with API key
+    cache::set_api_key("benchKey".to_string()).expect("Error setting API key");
+    let rates = providers::MockProvider.get_rates("EUR").expect("Error getting rates");
+    cache::add_rates(providers::exchangerate_api::ID, &rates).expect("Error adding rates");
+}
+
+fn run(name: &str, mut convert: impl FnMut(&str)) {
+    let start = Instant::now();
+    for i in 0..CONVERSIONS {
+        convert(CODES[i as usize % CODES.len()]);
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{:<28} {:>8.2?} total, {:>8.2?} per conversion",
+        name,
+        elapsed,
+        elapsed / CONVERSIONS
+    );
+}
+
+fn main() {
+    setup();
+    // How lookups were done before cache handle, connection opened for each of them
+    run("connection per lookup", |code| {
+        assert!(cache::check_code("PLN").expect("Error"));
+        assert!(cache::check_code(code).expect("Error"));
+        assert!(cache::check_exchange("PLN", code).expect("Error"));
+        cache::Cache::open().expect("Error").get_rate_info("PLN", code).expect("Error");
+        cache::get_rate("PLN", code).expect("Error");
+    });
+    run("cache handle per conversion", |code| {
+        let cache = cache::Cache::open().expect("Error opening cache");
+        convert_value(&cache, "PLN", code, "100").expect("Error converting");
+        cache.close();
+    });
+    let cache = cache::Cache::open().expect("Error opening cache");
+    run("shared cache handle", |code| {
+        convert_value(&cache, "PLN", code, "100").expect("Error converting");
+    });
+    cache.close();
+    let overrides = config::Overrides {
+        providers: Some(vec![providers::exchangerate_api::ID.to_string()]),
+        ..Default::default()
+    };
+    let cache = cache::Cache::open_with(overrides).expect("Error opening cache");
+    run("shared handle, API provider", |code| {
+        convert_value(&cache, "EUR", code, "100").expect("Error converting");
+    });
+    cache.close();
+}
@@ -1,162 +1,644 @@
-use std::{
-    collections::HashMap,
-    fs::{metadata, remove_file},
-};
+use std::fs::{metadata, remove_file};
 
-use rusqlite::{Connection, Result};
+use rusqlite::{params, Connection, OptionalExtension, Result, TransactionBehavior};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
 
-use crate::config::get_cache_path;
+use crate::config::{
+    get_cache_path, get_current_time, Overrides, CACHE_BUSY_TIMEOUT, REFRESH_LOCK_POLL,
+    REFRESH_LOCK_TIMEOUT,
+};
+use crate::providers::Rates;
 
 const CANNOT_CLOSE_MSG: &str = "Couldn't close sqlite connection";
 
-pub fn check_code(code: &String) -> Result<bool> {
-    let conn = Connection::open(get_cache_path())?;
-    let exists: bool = conn.query_row(
-        "SELECT EXISTS(SELECT code FROM currencies WHERE currencies.code = UPPER($1))",
-        [code],
-        |row| row.get(0),
-    )?;
-    conn.close().expect(CANNOT_CLOSE_MSG);
-
-    Ok(exists)
+/// Provider and times of cached rate
+#[derive(Debug, Clone, PartialEq)]
+pub struct Provenance {
+    pub provider: String,
+    /// Unix time provider published rate at, 0 if unknown
+    pub time_last_update: u64,
+    /// Unix time rate was fetched at, 0 if unknown
+    pub time_fetched: u64,
 }
 
-pub fn list_currencies() -> Result<Vec<[String; 2]>> {
-    let conn = Connection::open(get_cache_path())?;
-    let mut stmt = conn.prepare("SELECT code, text FROM currencies ORDER BY code")?;
-    let ret = stmt
-        .query_map([], |row| {
-            let v: Result<[String; 2]> = Ok([row.get(0)?, row.get(1)?]);
-            v
+impl Provenance {
+    fn from_row(row: &rusqlite::Row) -> Result<Provenance> {
+        Ok(Provenance {
+            provider: row.get(0)?,
+            time_last_update: row.get(1)?,
+            time_fetched: row.get(2)?,
         })
-        .expect("Error while listing currencies");
-
-    let mut result: Vec<[String; 2]> = Vec::new();
-    for code in ret {
-        let i = code.unwrap();
-        let z = [i[0].clone(), i[1].clone()];
-        result.push(z);
     }
-    stmt.finalize()?;
-    conn.close().expect(CANNOT_CLOSE_MSG);
-    Ok(result)
 }
 
-pub fn list_rates(code_from: &String) -> Result<Vec<[String; 2]>> {
-    let conn = Connection::open(get_cache_path())?;
-    let mut stmt = conn.prepare(
-        "SELECT code_to, rate FROM exchange_rates WHERE code_from = $1 ORDER BY code_to",
-    )?;
-    let ret = stmt
-        .query_map([code_from], |row| {
-            let v: Result<[String; 2]> = Ok([row.get(0)?, row.get(1)?]);
-            v
+/// Open cache keeping its prepared statements, for many lookups in a row. Free functions of
+/// this module open cache for single lookup
+pub struct Cache {
+    conn: Connection,
+    overrides: Overrides,
+    /// Unix time overrides were set at
+    time_overridden: u64,
+}
+
+impl Cache {
+    pub fn open() -> Result<Cache> {
+        Cache::open_with(Overrides::default())
+    }
+
+    /// Opens cache with `overrides` of configuration used by lookups through this handle
+    pub fn open_with(overrides: Overrides) -> Result<Cache> {
+        let conn = open()?;
+        conn.set_prepared_statement_cache_capacity(32);
+        Ok(Cache {
+            conn,
+            overrides,
+            time_overridden: get_current_time(),
         })
-        .expect("Error while listing rates");
+    }
 
-    let mut result: Vec<[String; 2]> = Vec::new();
-    for code in ret {
-        let i = code.unwrap();
-        let z = [i[0].clone(), i[1].clone()];
-        result.push(z);
+    pub fn overrides(&self) -> &Overrides {
+        &self.overrides
+    }
+
+    /// Unix time overrides were set at, rates fetched before it are refetched with
+    /// [`Overrides::refresh`]
+    pub fn time_overridden(&self) -> u64 {
+        self.time_overridden
+    }
+
+    pub fn set_overrides(&mut self, overrides: Overrides) {
+        self.overrides = overrides;
+        self.time_overridden = get_current_time();
+    }
+
+    pub fn close(self) {
+        self.conn.close().expect(CANNOT_CLOSE_MSG);
+    }
+
+    pub fn check_code(&self, code: &str) -> Result<bool> {
+        self.conn
+            .prepare_cached(
+                "SELECT EXISTS(SELECT code FROM currencies WHERE currencies.code = UPPER($1))",
+            )?
+            .query_row([code], |row| row.get(0))
+    }
+
+    pub fn list_currencies(&self) -> Result<Vec<[String; 2]>> {
+        let mut stmt = self.conn.prepare_cached("SELECT code, text FROM currencies ORDER BY code")?;
+        let result = stmt
+            .query_map([], |row| Ok([row.get(0)?, row.get(1)?]))?
+            .collect::<Result<Vec<[String; 2]>>>()?;
+        Ok(result)
+    }
+
+    pub fn list_rates(&self, code_from: &str) -> Result<Vec<[String; 2]>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT code_to, rate FROM exchange_rates WHERE code_from = $1 ORDER BY code_to",
+        )?;
+        let result = stmt
+            .query_map([code_from], |row| Ok([row.get(0)?, row.get(1)?]))?
+            .collect::<Result<Vec<[String; 2]>>>()?;
+        Ok(result)
+    }
+
+    pub fn check_exchange(&self, code_from: &str, code_to: &str) -> Result<bool> {
+        self.conn
+            .prepare_cached(
+                "SELECT EXISTS(SELECT code_from, code_to
+                    FROM exchange_rates
+                    WHERE exchange_rates.code_from = UPPER($1)
+                        AND exchange_rates.code_to = UPPER($2))",
+            )?
+            .query_row([code_from, code_to], |row| row.get(0))
+    }
+
+    pub fn get_rate(&self, code_from: &str, code_to: &str) -> Result<String> {
+        self.conn
+            .prepare_cached(
+                "SELECT rate
+                    FROM exchange_rates
+                    WHERE exchange_rates.code_from = UPPER($1)
+                        AND exchange_rates.code_to = UPPER($2)",
+            )?
+            .query_row([code_from, code_to], |row| row.get(0))
+    }
+
+    pub fn get_next_update(&self, code: &str) -> Result<u64> {
+        self.conn
+            .prepare_cached("SELECT next_update FROM currencies WHERE currencies.code = UPPER($1)")?
+            .query_row([code], |row| row.get(0))
+    }
+
+    /// Every cached rate fetched at or after `fetched_since`, or if it's `None`, that isn't past
+    /// next update at `time`, as `[code_from, code_to, rate, provider]`
+    pub fn list_fresh_rates(
+        &self,
+        time: u64,
+        fetched_since: Option<u64>,
+    ) -> Result<Vec<[String; 4]>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT code_from, code_to, rate, provider FROM exchange_rates
+                WHERE CASE WHEN ?2 IS NULL THEN next_update > ?1 ELSE time_fetched >= ?2 END
+                    AND code_from != code_to
+                ORDER BY next_update DESC",
+        )?;
+        let result = stmt
+            .query_map(params![time, fetched_since], |row| {
+                Ok([row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?])
+            })?
+            .collect::<Result<Vec<[String; 4]>>>()?;
+        Ok(result)
+    }
+
+    /// Unix time rates with `code` as base were last fetched at, 0 if never
+    pub fn get_rates_fetch_time(&self, code: &str) -> Result<u64> {
+        self.conn
+            .prepare_cached(
+                "SELECT time_fetched FROM currencies WHERE currencies.code = UPPER($1)",
+            )?
+            .query_row([code], |row| row.get(0))
+    }
+
+    /// Unix time cached rate was fetched at, 0 if unknown
+    pub fn get_rate_fetch_time(&self, code_from: &str, code_to: &str) -> Result<u64> {
+        self.conn
+            .prepare_cached(
+                "SELECT time_fetched
+                    FROM exchange_rates
+                    WHERE exchange_rates.code_from = UPPER($1)
+                        AND exchange_rates.code_to = UPPER($2)",
+            )?
+            .query_row([code_from, code_to], |row| row.get(0))
+    }
+
+    /// Unix time cached rate was published at, 0 if unknown
+    pub fn get_rate_time(&self, code_from: &str, code_to: &str) -> Result<u64> {
+        self.conn
+            .prepare_cached(
+                "SELECT time_last_update
+                    FROM exchange_rates
+                    WHERE exchange_rates.code_from = UPPER($1)
+                        AND exchange_rates.code_to = UPPER($2)",
+            )?
+            .query_row([code_from, code_to], |row| row.get(0))
+    }
+
+    /// Unix time latest cached rates with `code` as base were published at, 0 if unknown
+    pub fn get_rates_time(&self, code: &str) -> Result<u64> {
+        self.conn
+            .prepare_cached(
+                "SELECT COALESCE(MAX(time_last_update), 0)
+                    FROM exchange_rates
+                    WHERE exchange_rates.code_from = UPPER($1)",
+            )?
+            .query_row([code], |row| row.get(0))
+    }
+
+    /// Provider and next update time of cached rate
+    pub fn get_rate_info(&self, code_from: &str, code_to: &str) -> Result<(String, u64)> {
+        self.conn
+            .prepare_cached(
+                "SELECT provider, next_update
+                    FROM exchange_rates
+                    WHERE exchange_rates.code_from = UPPER($1)
+                        AND exchange_rates.code_to = UPPER($2)",
+            )?
+            .query_row([code_from, code_to], |row| Ok((row.get(0)?, row.get(1)?)))
+    }
+
+    /// Where cached rate comes from, for tracing converted figures
+    pub fn get_rate_provenance(&self, code_from: &str, code_to: &str) -> Result<Provenance> {
+        self.conn
+            .prepare_cached(
+                "SELECT provider, time_last_update, time_fetched
+                    FROM exchange_rates
+                    WHERE exchange_rates.code_from = UPPER($1)
+                        AND exchange_rates.code_to = UPPER($2)",
+            )?
+            .query_row([code_from, code_to], Provenance::from_row)
+    }
+
+    /// Provider, table number and effective date of cached rates with `code` as base
+    pub fn get_rates_info(&self, code: &str) -> Result<(String, Option<String>, Option<String>)> {
+        self.conn
+            .prepare_cached(
+                "SELECT provider, table_no, effective_date
+                    FROM currencies
+                    WHERE currencies.code = UPPER($1)",
+            )?
+            .query_row([code], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+    }
+
+    pub fn check_historical_exchange(
+        &self,
+        code_from: &str,
+        code_to: &str,
+        date: &str,
+    ) -> Result<bool> {
+        self.conn
+            .prepare_cached(
+                "SELECT EXISTS(SELECT code_from, code_to
+                    FROM historical_rates
+                    WHERE code_from = UPPER($1) AND code_to = UPPER($2) AND date = $3)",
+            )?
+            .query_row([code_from, code_to, date], |row| row.get(0))
+    }
+
+    /// Rate, provider and effective date of pair cached for `date`
+    pub fn get_historical_rate(
+        &self,
+        code_from: &str,
+        code_to: &str,
+        date: &str,
+    ) -> Result<(String, String, Option<String>)> {
+        self.conn
+            .prepare_cached(
+                "SELECT rate, provider, effective_date
+                    FROM historical_rates
+                    WHERE code_from = UPPER($1) AND code_to = UPPER($2) AND date = $3",
+            )?
+            .query_row([code_from, code_to, date], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+    }
+
+    /// Same as [`Cache::get_rate_provenance`] for rate cached for `date`
+    pub fn get_historical_rate_provenance(
+        &self,
+        code_from: &str,
+        code_to: &str,
+        date: &str,
+    ) -> Result<Provenance> {
+        self.conn
+            .prepare_cached(
+                "SELECT provider, time_last_update, time_fetched
+                    FROM historical_rates
+                    WHERE code_from = UPPER($1) AND code_to = UPPER($2) AND date = $3",
+            )?
+            .query_row([code_from, code_to, date], Provenance::from_row)
+    }
+
+    pub fn list_historical_rates(&self, code_from: &str, date: &str) -> Result<Vec<[String; 2]>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT code_to, rate FROM historical_rates
+                WHERE code_from = UPPER($1) AND date = $2
+                ORDER BY code_to",
+        )?;
+        let result = stmt
+            .query_map([code_from, date], |row| Ok([row.get(0)?, row.get(1)?]))?
+            .collect::<Result<Vec<[String; 2]>>>()?;
+        Ok(result)
+    }
+
+    /// Time series of pair published since `since` as `(time, rate, provider)`, oldest first
+    pub fn get_rate_history(
+        &self,
+        code_from: &str,
+        code_to: &str,
+        since: u64,
+    ) -> Result<Vec<(u64, String, String)>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT time_last_update, rate, provider FROM rate_history
+                WHERE code_from = UPPER($1) AND code_to = UPPER($2) AND time_last_update >= $3
+                ORDER BY time_last_update",
+        )?;
+        let result = stmt
+            .query_map(params![code_from, code_to, since], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })?
+            .collect::<Result<Vec<(u64, String, String)>>>()?;
+        Ok(result)
+    }
+
+    pub fn get_api_key(&self) -> Result<String> {
+        self.conn
+            .prepare_cached("SELECT value FROM config WHERE config.name = 'API_KEY'")?
+            .query_row([], |row| row.get(0))
+    }
+
+    pub fn get_config(&self, name: &str) -> Result<Option<String>> {
+        self.conn
+            .prepare_cached("SELECT value FROM config WHERE config.name = $1")?
+            .query_row([name], |row| row.get(0))
+            .optional()
     }
-    stmt.finalize()?;
-    conn.close().expect(CANNOT_CLOSE_MSG);
-    Ok(result)
 }
 
-pub fn check_exchange(code_from: &String, code_to: &String) -> Result<bool> {
-    let conn = Connection::open(get_cache_path())?;
-    let exists: bool = conn.query_row(
-        "SELECT EXISTS(SELECT code_from, code_to 
-            FROM exchange_rates 
-            WHERE exchange_rates.code_from = UPPER($1) AND exchange_rates.code_to = UPPER($2))",
-        [code_from, code_to],
-        |row| row.get(0),
-    )?;
-    conn.close().expect(CANNOT_CLOSE_MSG);
+pub fn check_code(code: &str) -> Result<bool> {
+    Cache::open()?.check_code(code)
+}
 
-    Ok(exists)
+pub fn list_currencies() -> Result<Vec<[String; 2]>> {
+    Cache::open()?.list_currencies()
 }
 
-pub fn get_rate(code_from: &String, code_to: &String) -> Result<String> {
-    let conn = Connection::open(get_cache_path())?;
-    let rate: String = conn.query_row(
-        "SELECT rate 
-            FROM exchange_rates 
-            WHERE exchange_rates.code_from = UPPER($1) AND exchange_rates.code_to = UPPER($2)",
-        [code_from, code_to],
-        |row| row.get(0),
-    )?;
-    conn.close().expect(CANNOT_CLOSE_MSG);
+pub fn list_rates(code_from: &str) -> Result<Vec<[String; 2]>> {
+    Cache::open()?.list_rates(code_from)
+}
 
-    Ok(rate)
+pub fn check_exchange(code_from: &str, code_to: &str) -> Result<bool> {
+    Cache::open()?.check_exchange(code_from, code_to)
 }
-pub fn get_next_update(code: &String) -> Result<u64> {
-    let conn = Connection::open(get_cache_path())?;
-    let next_update: u64 = conn.query_row(
-        "SELECT next_update FROM currencies WHERE currencies.code = UPPER($1)",
-        [code],
-        |row| row.get(0),
-    )?;
-    conn.close().expect(CANNOT_CLOSE_MSG);
 
-    Ok(next_update)
+pub fn get_rate(code_from: &str, code_to: &str) -> Result<String> {
+    Cache::open()?.get_rate(code_from, code_to)
 }
 
-pub fn add_rates(
-    next_update: u64,
-    code_from: &String,
-    rates: &HashMap<String, serde_json::Value>,
-) -> Result<()> {
-    let conn = Connection::open(get_cache_path())?;
+/// Canonical form rates are stored in, without trailing zeros or exponent
+fn to_text(rate: &Decimal) -> String {
+    rate.normalize().to_string()
+}
 
-    for (code_to, rate) in rates {
+/// Adds rates to time series, rates with unknown publication time are kept as of now
+fn insert_history(conn: &Connection, provider: &str, rates: &Rates) -> Result<()> {
+    let time = match rates.time_last_update_unix {
+        0 => get_current_time(),
+        time => time,
+    };
+    for (code_to, rate) in &rates.conversion_rates {
         conn.execute(
             "
-    INSERT OR REPLACE INTO exchange_rates(code_from,code_to,rate)
-    VALUES(UPPER($1),UPPER($2),$3)
+    INSERT OR REPLACE INTO rate_history(code_from,code_to,time_last_update,rate,provider)
+    VALUES(UPPER($1),UPPER($2),$3,$4,$5)
     ",
-            [code_from, code_to, &rate.to_string()],
+            params![rates.base_code, code_to, time, to_text(rate), provider],
         )?;
     }
-    conn.execute(
+    Ok(())
+}
+
+/// Replaces latest rates, keeping replaced ones in time series
+fn insert_rates(conn: &Connection, provider: &str, rates: &Rates) -> Result<()> {
+    insert_history(conn, provider, rates)?;
+    for (code_to, rate) in &rates.conversion_rates {
+        conn.execute(
+            "
+    INSERT OR REPLACE INTO exchange_rates(
+        code_from,code_to,rate,time_last_update,next_update,provider,time_fetched
+    )
+    VALUES(UPPER($1),UPPER($2),$3,$4,$5,$6,$7)
+    ",
+            params![
+                rates.base_code,
+                code_to,
+                to_text(rate),
+                rates.time_last_update_unix,
+                rates.time_next_update_unix,
+                provider,
+                get_current_time()
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+/// Caches whole rate table of base currency in single transaction, so readers see either
+/// whole old table or whole new one and interrupted refresh leaves old one in place. Pairs
+/// missing from new table are removed, their rates stay in time series
+pub fn add_rates(provider: &str, rates: &Rates) -> Result<()> {
+    let mut conn = open()?;
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+    tx.execute(
+        "DELETE FROM exchange_rates WHERE code_from = UPPER($1)",
+        [&rates.base_code],
+    )?;
+    insert_rates(&tx, provider, rates)?;
+    tx.execute(
         "
     UPDATE currencies
-    SET next_update = $1
-    WHERE currencies.code = UPPER($2)
+    SET next_update = $1, provider = $2, table_no = $3, effective_date = $4, time_fetched = $5
+    WHERE currencies.code = UPPER($6)
     ",
-        [&next_update.to_string(), code_from],
+        params![
+            rates.time_next_update_unix,
+            provider,
+            rates.table_no,
+            rates.effective_date,
+            get_current_time(),
+            rates.base_code
+        ],
     )?;
+    tx.commit()?;
+    conn.close().expect(CANNOT_CLOSE_MSG);
+    Ok(())
+}
+
+/// Caches single pairs without marking whole rate table of base currency as updated
+pub fn add_pair_rates(provider: &str, rates: &Rates) -> Result<()> {
+    let mut conn = open()?;
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+    insert_rates(&tx, provider, rates)?;
+    tx.commit()?;
     conn.close().expect(CANNOT_CLOSE_MSG);
     Ok(())
 }
 
+/// Caches rate table of base currency as it was on `date`, separately from current rates
+pub fn add_historical_rates(provider: &str, date: &str, rates: &Rates) -> Result<()> {
+    let mut conn = open()?;
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    insert_history(&tx, provider, rates)?;
+    for (code_to, rate) in &rates.conversion_rates {
+        tx.execute(
+            "
+    INSERT OR REPLACE INTO historical_rates(
+        code_from,code_to,date,rate,provider,effective_date,time_last_update,time_fetched
+    )
+    VALUES(UPPER($1),UPPER($2),$3,$4,$5,$6,$7,$8)
+    ",
+            params![
+                rates.base_code,
+                code_to,
+                date,
+                to_text(rate),
+                provider,
+                rates.effective_date,
+                rates.time_last_update_unix,
+                get_current_time()
+            ],
+        )?;
+    }
+    tx.commit()?;
+    conn.close().expect(CANNOT_CLOSE_MSG);
+    Ok(())
+}
+
+/// Cached currency with update details, as stored in snapshots
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct CurrencyRow {
+    pub code: String,
+    pub text: String,
+    pub next_update: u64,
+    pub provider: String,
+    pub table_no: Option<String>,
+    pub effective_date: Option<String>,
+    /// Unix time rates were fetched at, 0 if unknown
+    #[serde(default)]
+    pub time_fetched: u64,
+}
+
+/// Cached rate with update details, as stored in snapshots
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct RateRow {
+    pub code_from: String,
+    pub code_to: String,
+    pub rate: String,
+    /// Unix time rate was published at, 0 if unknown
+    #[serde(default)]
+    pub time_last_update: u64,
+    pub next_update: u64,
+    pub provider: String,
+    /// Unix time rate was fetched at, 0 if unknown
+    #[serde(default)]
+    pub time_fetched: u64,
+}
+
+pub fn list_currency_rows() -> Result<Vec<CurrencyRow>> {
+    let conn = open()?;
+    let mut stmt = conn.prepare(
+        "SELECT code, text, next_update, provider, table_no, effective_date, time_fetched
+            FROM currencies ORDER BY code",
+    )?;
+    let result = stmt
+        .query_map([], |row| {
+            Ok(CurrencyRow {
+                code: row.get(0)?,
+                text: row.get(1)?,
+                next_update: row.get(2)?,
+                provider: row.get(3)?,
+                table_no: row.get(4)?,
+                effective_date: row.get(5)?,
+                time_fetched: row.get(6)?,
+            })
+        })?
+        .collect::<Result<Vec<CurrencyRow>>>()?;
+    stmt.finalize()?;
+    conn.close().expect(CANNOT_CLOSE_MSG);
+    Ok(result)
+}
+
+pub fn list_rate_rows() -> Result<Vec<RateRow>> {
+    let conn = open()?;
+    let mut stmt = conn.prepare(
+        "SELECT code_from, code_to, rate, time_last_update, next_update, provider, time_fetched
+            FROM exchange_rates ORDER BY code_from, code_to",
+    )?;
+    let result = stmt
+        .query_map([], |row| {
+            Ok(RateRow {
+                code_from: row.get(0)?,
+                code_to: row.get(1)?,
+                rate: row.get(2)?,
+                time_last_update: row.get(3)?,
+                next_update: row.get(4)?,
+                provider: row.get(5)?,
+                time_fetched: row.get(6)?,
+            })
+        })?
+        .collect::<Result<Vec<RateRow>>>()?;
+    stmt.finalize()?;
+    conn.close().expect(CANNOT_CLOSE_MSG);
+    Ok(result)
+}
+
+/// Merges rows into cache in single transaction. Existing rows are replaced only by newer ones,
+/// rates by publication time and then next update, currencies by next update.
+/// Returns number of currencies and rates added or replaced
+pub fn merge_rows(currencies: &[CurrencyRow], rates: &[RateRow]) -> Result<(usize, usize)> {
+    let mut conn = open()?;
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let mut merged = (0, 0);
+    for currency in currencies {
+        merged.0 += tx.execute(
+            "
+    INSERT INTO currencies(code,text,next_update,provider,table_no,effective_date,time_fetched)
+    VALUES(UPPER($1),$2,$3,$4,$5,$6,$7)
+    ON CONFLICT(code) DO UPDATE SET
+        next_update = excluded.next_update,
+        provider = excluded.provider,
+        table_no = excluded.table_no,
+        effective_date = excluded.effective_date,
+        time_fetched = excluded.time_fetched
+    WHERE excluded.next_update > currencies.next_update
+    ",
+            params![
+                currency.code,
+                currency.text,
+                currency.next_update,
+                currency.provider,
+                currency.table_no,
+                currency.effective_date,
+                currency.time_fetched
+            ],
+        )?;
+    }
+    for rate in rates {
+        merged.1 += tx.execute(
+            "
+    INSERT INTO exchange_rates(
+        code_from,code_to,rate,time_last_update,next_update,provider,time_fetched
+    )
+    VALUES(UPPER($1),UPPER($2),$3,$4,$5,$6,$7)
+    ON CONFLICT(code_from, code_to) DO UPDATE SET
+        rate = excluded.rate,
+        time_last_update = excluded.time_last_update,
+        next_update = excluded.next_update,
+        provider = excluded.provider,
+        time_fetched = excluded.time_fetched
+    WHERE (excluded.time_last_update, excluded.next_update)
+        > (exchange_rates.time_last_update, exchange_rates.next_update)
+    ",
+            params![
+                rate.code_from,
+                rate.code_to,
+                rate.rate,
+                rate.time_last_update,
+                rate.next_update,
+                rate.provider,
+                rate.time_fetched
+            ],
+        )?;
+        if rate.time_last_update > 0 {
+            tx.execute(
+                "
+    INSERT OR IGNORE INTO rate_history(code_from,code_to,time_last_update,rate,provider)
+    VALUES(UPPER($1),UPPER($2),$3,$4,$5)
+    ",
+                params![
+                    rate.code_from,
+                    rate.code_to,
+                    rate.time_last_update,
+                    rate.rate,
+                    rate.provider
+                ],
+            )?;
+        }
+    }
+    tx.commit()?;
+    conn.close().expect(CANNOT_CLOSE_MSG);
+    Ok(merged)
+}
+
 pub fn add_code(code: [String; 2]) -> Result<()> {
-    let conn = Connection::open(get_cache_path())?;
+    let conn = open()?;
     conn.execute(
         "
     INSERT OR IGNORE INTO currencies(code,text,next_update)
     VALUES(UPPER($1),$2,0)
     ",
-        [code.get(0), code.get(1)],
+        [&code[0], &code[1]],
     )?;
     conn.close().expect(CANNOT_CLOSE_MSG);
     Ok(())
 }
 pub fn get_api_key() -> Result<String> {
-    let conn = Connection::open(get_cache_path())?;
-    let api_key: String = conn.query_row(
-        "SELECT value FROM config WHERE config.name = 'API_KEY'",
-        [],
-        |row| row.get(0),
-    )?;
-    conn.close().expect(CANNOT_CLOSE_MSG);
-
-    Ok(api_key)
+    Cache::open()?.get_api_key()
 }
 pub fn set_api_key(key: String) -> Result<()> {
-    let conn = Connection::open(get_cache_path())?;
+    let conn = open()?;
     conn.execute(
         "
     UPDATE config
@@ -169,7 +651,69 @@ pub fn set_api_key(key: String) -> Result<()> {
 
     Ok(())
 }
+pub fn get_config(name: &str) -> Result<Option<String>> {
+    Cache::open()?.get_config(name)
+}
+pub fn set_config(name: &str, value: &str) -> Result<()> {
+    let conn = open()?;
+    conn.execute(
+        "INSERT OR REPLACE INTO config (name, value) VALUES ($1, $2)",
+        [name, value],
+    )?;
+    conn.close().expect(CANNOT_CLOSE_MSG);
+
+    Ok(())
+}
+
+/// Takes refresh lock of `code` if it's free or expired, returns whether it was taken
+fn try_refresh_lock(code: &str, owner: &str) -> Result<bool> {
+    let conn = open()?;
+    let now = get_current_time();
+    let taken = conn.execute(
+        "
+    INSERT INTO refresh_locks(code,owner,locked_until) VALUES(UPPER($1),$2,$3)
+    ON CONFLICT(code) DO UPDATE SET
+        owner = excluded.owner,
+        locked_until = excluded.locked_until
+    WHERE refresh_locks.locked_until <= $4
+    ",
+        params![code, owner, now + REFRESH_LOCK_TIMEOUT, now],
+    )?;
+    conn.close().expect(CANNOT_CLOSE_MSG);
+
+    Ok(taken == 1)
+}
+
+/// Runs `f` holding refresh lock of `code`, waiting until other process holding it is done.
+/// `f` should check whether refresh is still needed, as lock holder may have just done it
+pub fn with_refresh_lock<T, E: From<rusqlite::Error>>(
+    code: &str,
+    f: impl FnOnce() -> std::result::Result<T, E>,
+) -> std::result::Result<T, E> {
+    let owner = format!("{}-{:?}", std::process::id(), std::thread::current().id());
+    while !try_refresh_lock(code, &owner)? {
+        std::thread::sleep(REFRESH_LOCK_POLL);
+    }
+    let result = f();
+    let conn = open()?;
+    conn.execute(
+        "DELETE FROM refresh_locks WHERE code = UPPER($1) AND owner = $2",
+        [code, &owner],
+    )?;
+    conn.close().expect(CANNOT_CLOSE_MSG);
+
+    result
+}
+
+/// Creates cache if it doesn't exist yet, leaving cache created meanwhile by other process as is
+pub fn init_cache() -> Result<()> {
+    open()?.close().expect(CANNOT_CLOSE_MSG);
+
+    Ok(())
+}
 
+/// Recreates cache from scratch, losing API key and all cached rates. Caches created by older
+/// versions are upgraded in place when opened, so this is only needed for broken caches
 pub fn create_cache() -> Result<()> {
     let path = &get_cache_path();
     if path.is_dir() {
@@ -188,8 +732,77 @@ pub fn create_cache() -> Result<()> {
             },
         }
     }
-    let conn = Connection::open(path)?;
+    // Journal of removed cache must not be applied to new one
+    for suffix in ["-wal", "-shm"] {
+        let mut journal = path.clone().into_os_string();
+        journal.push(suffix);
+        let _ = remove_file(journal);
+    }
+    open()?.close().expect(CANNOT_CLOSE_MSG);
 
+    Ok(())
+}
+
+/// Version of cache layout, stored in `user_version` pragma of the database
+pub const SCHEMA_VERSION: u32 = 8;
+
+/// Migration from version `i` to `i + 1` is at index `i`. Each one has to be safe to run on
+/// cache that already has some of its changes, as caches before versioning have no version set
+const MIGRATIONS: [fn(&Connection) -> Result<()>; SCHEMA_VERSION as usize] =
+    [
+        create_tables,
+        add_provider_columns,
+        create_historical_rates,
+        create_rate_history,
+        add_rate_update_time,
+        create_refresh_locks,
+        add_fetch_time,
+        add_historical_provenance,
+    ];
+
+/// Opens cache shared with other processes, upgrading its layout first if it's from older version
+fn open() -> Result<Connection> {
+    let mut conn = Connection::open(get_cache_path())?;
+    conn.busy_timeout(CACHE_BUSY_TIMEOUT)?;
+    conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+    migrate(&mut conn)?;
+    Ok(conn)
+}
+
+/// Runs migrations missing in `conn`, each in its own transaction. Version is read again under
+/// write lock, so concurrent processes don't run same migration twice
+pub fn migrate(conn: &mut Connection) -> Result<()> {
+    let version = |conn: &Connection| -> Result<u32> {
+        conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+    };
+    while version(conn)? < SCHEMA_VERSION {
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let i = version(&tx)?;
+        if let Some(migration) = MIGRATIONS.get(i as usize) {
+            migration(&tx)?;
+            tx.pragma_update(None, "user_version", i + 1)?;
+        }
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+fn add_column(conn: &Connection, table: &str, column: &str, definition: &str) -> Result<()> {
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT name FROM pragma_table_info($1) WHERE name = $2)",
+        [table, column],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            (),
+        )?;
+    }
+    Ok(())
+}
+
+fn create_tables(conn: &Connection) -> Result<()> {
     conn.execute(
         "
     CREATE TABLE IF NOT EXISTS config (
@@ -229,7 +842,81 @@ pub fn create_cache() -> Result<()> {
     ",
         (),
     )?;
-    conn.close().expect(CANNOT_CLOSE_MSG);
+    Ok(())
+}
+
+fn add_provider_columns(conn: &Connection) -> Result<()> {
+    add_column(conn, "currencies", "provider", "TEXT NOT NULL DEFAULT ''")?;
+    add_column(conn, "currencies", "table_no", "TEXT")?;
+    add_column(conn, "currencies", "effective_date", "TEXT")?;
+    add_column(conn, "exchange_rates", "next_update", "TIME NOT NULL DEFAULT 0")?;
+    add_column(conn, "exchange_rates", "provider", "TEXT NOT NULL DEFAULT ''")?;
+    Ok(())
+}
+
+fn create_historical_rates(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "
+    CREATE TABLE IF NOT EXISTS historical_rates (
+        code_from   TEXT NOT NULL,
+        code_to   TEXT NOT NULL,
+        date    TEXT NOT NULL,
+        rate    TEXT NOT NULL,
+        provider    TEXT NOT NULL DEFAULT '',
+        effective_date  TEXT,
+        PRIMARY KEY (code_from, code_to, date)
+    )",
+        (),
+    )?;
+    Ok(())
+}
+
+/// Time series of rates, latest ones are also kept in `exchange_rates` for fast lookup
+fn create_rate_history(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "
+    CREATE TABLE IF NOT EXISTS rate_history (
+        code_from   TEXT NOT NULL,
+        code_to   TEXT NOT NULL,
+        time_last_update  TIME NOT NULL,
+        rate    TEXT NOT NULL,
+        provider    TEXT NOT NULL DEFAULT '',
+        PRIMARY KEY (code_from, code_to, time_last_update)
+    )",
+        (),
+    )?;
+    Ok(())
+}
+
+/// Publication time of latest rates, so newer of two cached rates can be told apart
+fn add_rate_update_time(conn: &Connection) -> Result<()> {
+    add_column(conn, "exchange_rates", "time_last_update", "TIME NOT NULL DEFAULT 0")
+}
 
+/// Bases being refreshed, so concurrent processes fetch them once. Lock expires on its own
+/// in case process holding it was killed
+fn create_refresh_locks(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "
+    CREATE TABLE IF NOT EXISTS refresh_locks (
+        code   TEXT PRIMARY KEY,
+        owner   TEXT NOT NULL,
+        locked_until  TIME NOT NULL
+    )",
+        (),
+    )?;
     Ok(())
 }
+
+/// When rates were fetched, so they can expire after configured time instead of provider's next
+/// update. Rates cached before are treated as never fetched
+fn add_fetch_time(conn: &Connection) -> Result<()> {
+    add_column(conn, "currencies", "time_fetched", "TIME NOT NULL DEFAULT 0")?;
+    add_column(conn, "exchange_rates", "time_fetched", "TIME NOT NULL DEFAULT 0")
+}
+
+/// Publication and fetch time of historical rates, so they can be traced like current ones
+fn add_historical_provenance(conn: &Connection) -> Result<()> {
+    add_column(conn, "historical_rates", "time_last_update", "TIME NOT NULL DEFAULT 0")?;
+    add_column(conn, "historical_rates", "time_fetched", "TIME NOT NULL DEFAULT 0")
+}
@@ -1,11 +1,54 @@
 use std::{
     env::{temp_dir, var_os},
     path::PathBuf,
+    time::Duration,
 };
 
 pub const CACHE_LOCATION_ENV_NAME: &str = "CURRENCY_CACHE";
 pub const REST_ENDPOINT: &str = "https://v6.exchangerate-api.com/v6/";
+pub const NBP_ENDPOINT: &str = "https://api.nbp.pl/api/exchangerates/tables/";
+pub const ECB_DAILY_ENDPOINT: &str = "https://www.ecb.europa.eu/stats/eurofxref/eurofxref-daily.xml";
+pub const ECB_HIST_ENDPOINT: &str = "https://www.ecb.europa.eu/stats/eurofxref/eurofxref-hist.xml";
 pub const REST_ENDPOINT_ENV_NAME: &str = "CURRENCY_ENDPOINT";
+pub const PROVIDER_ENV_NAME: &str = "CURRENCY_PROVIDER";
+pub const PROVIDER_CONFIG_NAME: &str = "PROVIDER";
+pub const FETCH_STRATEGY_ENV_NAME: &str = "CURRENCY_FETCH_STRATEGY";
+pub const FETCH_STRATEGY_CONFIG_NAME: &str = "FETCH_STRATEGY";
+pub const RATES_FILE_ENV_NAME: &str = "CURRENCY_RATES_FILE";
+pub const RATES_FILE_CONFIG_NAME: &str = "RATES_FILE";
+pub const MAX_HOPS_ENV_NAME: &str = "CURRENCY_MAX_HOPS";
+pub const MAX_HOPS_CONFIG_NAME: &str = "MAX_HOPS";
+pub const DEFAULT_MAX_HOPS: usize = 3;
+pub const WATCHLIST_ENV_NAME: &str = "CURRENCY_WATCHLIST";
+pub const WATCHLIST_CONFIG_NAME: &str = "WATCHLIST";
+pub const TTL_ENV_NAME: &str = "CURRENCY_TTL";
+pub const TTL_CONFIG_NAME: &str = "TTL";
+pub const MAX_STALE_ENV_NAME: &str = "CURRENCY_MAX_STALE";
+pub const QUOTA_CONFIG_NAME: &str = "QUOTA";
+/// Percentage of monthly API quota below which warning is shown before fetching
+pub const QUOTA_LOW_PERCENT: u64 = 10;
+/// How long cache access waits for other process writing to it
+pub const CACHE_BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+/// Seconds after which refresh lock of process that didn't release it can be taken over
+pub const REFRESH_LOCK_TIMEOUT: u64 = 60;
+/// How often process waiting for refresh lock checks it
+pub const REFRESH_LOCK_POLL: Duration = Duration::from_millis(50);
+
+/// Settings of single cache handle taking precedence over env variables and configuration in
+/// cache, like `--provider`, `--max-age`, `--refresh` and `--max-stale` do for single run
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Overrides {
+    /// Provider chain in priority order
+    pub providers: Option<Vec<String>>,
+    /// Refetch rates fetched longer ago than this many seconds, regardless of TTL and provider's
+    /// next update
+    pub max_age: Option<u64>,
+    /// Refetch rates fetched before overrides were set
+    pub refresh: bool,
+    /// Fail instead of using cached rates published longer ago than this many seconds when
+    /// provider is unreachable
+    pub max_stale: Option<u64>,
+}
 
 #[cfg(not(windows))]
 #[macro_export]
@@ -20,13 +63,10 @@ macro_rules! main_separator{
 }
 
 pub fn get_endpoint() -> String {
-    let ret: String;
     match var_os(REST_ENDPOINT_ENV_NAME) {
-        Some(val) => ret = val.to_str().unwrap().to_string(),
-        None => ret = REST_ENDPOINT.to_string(),
+        Some(val) => val.to_str().unwrap().to_string(),
+        None => REST_ENDPOINT.to_string(),
     }
-
-    ret
 }
 pub fn get_cache_path() -> PathBuf {
     let mut path: PathBuf = PathBuf::new();
@@ -53,3 +93,91 @@ pub fn get_current_time() -> u64 {
         .unwrap_or_default()
         .as_secs()
 }
+
+/// Unix time of midnight UTC of date in `YYYY-MM-DD` format
+pub fn parse_date(date: &str) -> Option<u64> {
+    let mut parts = date.trim().splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+    let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+    let month_days = [31, if leap { 29 } else { 28 }, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+    if year < 1970 || !(1..=12).contains(&month) {
+        return None;
+    }
+    if !(1..=month_days[month as usize - 1]).contains(&day) {
+        return None;
+    }
+    // Days from civil algorithm, see http://howardhinnant.github.io/date_algorithms.html
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    Some(days as u64 * 86400)
+}
+
+/// Date in `YYYY-MM-DD` format of unix time
+pub fn format_date(time: u64) -> String {
+    // Civil from days algorithm, see http://howardhinnant.github.io/date_algorithms.html
+    let z = (time / 86400) as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Formats unix time as `YYYY-MM-DD HH:MM` in UTC
+pub fn format_time(time: u64) -> String {
+    format!(
+        "{} {:02}:{:02}",
+        format_date(time),
+        time % 86400 / 3600,
+        time % 3600 / 60
+    )
+}
+
+/// Seconds of duration like `90s`, `45m`, `36h` or `2d`, number without unit is in seconds
+pub fn parse_duration(duration: &str) -> Option<u64> {
+    let duration = duration.trim();
+    let (number, unit) = match duration.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => duration.split_at(i),
+        None => (duration, "s"),
+    };
+    let unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return None,
+    };
+    number.parse::<u64>().ok()?.checked_mul(unit)
+}
+
+/// Formats seconds as two largest units, like `2d 3h` or `5m 10s`, smaller ones are dropped
+pub fn format_duration(secs: u64) -> String {
+    let units = [
+        (secs / 86400, "d"),
+        (secs % 86400 / 3600, "h"),
+        (secs % 3600 / 60, "m"),
+        (secs % 60, "s"),
+    ];
+    let parts: Vec<String> = units
+        .iter()
+        .skip_while(|(value, _)| *value == 0)
+        .take(2)
+        .filter(|(value, _)| *value > 0)
+        .map(|(value, unit)| format!("{}{}", value, unit))
+        .collect();
+    if parts.is_empty() {
+        return "0s".to_string();
+    }
+    parts.join(" ")
+}
@@ -0,0 +1,127 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Currency code is not known to the cache or provider
+    UnknownCode(String),
+    /// Currency code is known, but not in ISO formats
+    NotIso(String),
+    /// Amount is not a number
+    InvalidAmount(String),
+    /// Date is not in `YYYY-MM-DD` format or is in the future
+    InvalidDate(String),
+    /// Provider has no rates for date
+    NoHistoricalRates(String),
+    /// Provider name is not recognized
+    UnknownProvider(String),
+    InvalidKey,
+    QuotaReached,
+    /// Provider responded with error not covered by other variants
+    Api(String),
+    Network(reqwest::Error),
+    Cache(rusqlite::Error),
+    MalformedResponse(String),
+    /// Every provider in chain failed, with error of each
+    AllProvidersFailed(Vec<(String, Error)>),
+    /// Rates file can't be read or has invalid entries
+    RatesFile { path: String, errors: Vec<String> },
+    /// Snapshot can't be read or written, or has invalid rows
+    Snapshot { path: String, errors: Vec<String> },
+    /// Rates of currency can't be fetched and cached ones are older than `--max-stale` allows
+    StaleRates(String, Box<Error>),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnknownCode(code) => write!(f, "Code {} doesn't exists, use correct code!", code),
+            Error::NotIso(code) => write!(f, "{} not found in ISO formats", code),
+            Error::InvalidAmount(amount) => write!(f, "{} is not a number!", amount),
+            Error::InvalidDate(date) => {
+                write!(f, "{} is not a valid date, use YYYY-MM-DD format", date)
+            }
+            Error::NoHistoricalRates(date) => write!(f, "No rates available for {}", date),
+            Error::UnknownProvider(name) if name.is_empty() => write!(f, "No provider selected"),
+            Error::UnknownProvider(name) => write!(f, "Unknown provider {}", name),
+            Error::InvalidKey => write!(f, "Api Key is invalid"),
+            Error::QuotaReached => write!(f, "Exceeded API limit"),
+            Error::Api(error_type) => write!(f, "API responded with error: {}", error_type),
+            Error::Network(e) => write!(f, "Network error: {}", e),
+            Error::Cache(e) => write!(f, "Cache error: {}", e),
+            Error::MalformedResponse(msg) => write!(f, "Malformed response: {}", msg),
+            Error::AllProvidersFailed(errors) => {
+                write!(f, "All providers failed:")?;
+                for (name, error) in errors {
+                    write!(f, "\n  {}: {}", name, error)?;
+                }
+                Ok(())
+            }
+            Error::RatesFile { path, errors } => {
+                if path.is_empty() {
+                    write!(f, "Invalid rates file:")?;
+                } else {
+                    write!(f, "Invalid rates file {}:", path)?;
+                }
+                for error in errors {
+                    write!(f, "\n  {}", error)?;
+                }
+                Ok(())
+            }
+            Error::Snapshot { path, errors } => {
+                write!(f, "Can't use snapshot {}:", path)?;
+                for error in errors {
+                    write!(f, "\n  {}", error)?;
+                }
+                Ok(())
+            }
+            Error::StaleRates(code, e) => write!(
+                f,
+                "Cached rates of {} are too old to use with --max-stale, and fetching failed: {}",
+                code, e
+            ),
+        }
+    }
+}
+
+impl Error {
+    /// Whether provider couldn't be reached or ran out of quota, so cached rates past their
+    /// next update are still better than nothing
+    pub fn is_unavailable(&self) -> bool {
+        match self {
+            Error::Network(_) | Error::QuotaReached => true,
+            Error::AllProvidersFailed(errors) => errors.iter().all(|(_, e)| e.is_unavailable()),
+            _ => false,
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Network(e) => Some(e),
+            Error::Cache(e) => Some(e),
+            Error::StaleRates(_, e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Network(e)
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Error::Cache(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::MalformedResponse(e.to_string())
+    }
+}
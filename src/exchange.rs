@@ -1,76 +1,776 @@
+use std::collections::{HashMap, VecDeque};
+use std::env::var_os;
+
+use crate::cache::Cache;
+use crate::error::{Error, Result};
+use crate::requests::FetchStrategy;
 use crate::*;
 use rust_decimal::prelude::*;
 use rusty_money::{iso::find, ExchangeRate, Money};
-pub struct Result {
+
+pub struct Conversion {
+    pub code_from: String,
+    pub code_to: String,
+    pub amount: Decimal,
+    pub value: Decimal,
+    pub rate: Decimal,
+    /// Input amount formatted as money
     pub from: String,
+    /// Converted amount formatted as money
     pub to: String,
-    pub rate: String
-}
-
-pub fn update_rate(code: &String) {
-    if cache::get_next_update(code).expect("Error getting next update time from cache")
-        <= config::get_current_time()
-    {
-        let status = requests::get_rates(code)
-            .expect("Error while fetching rates");
-        if status == requests::Status::INVALID {
-            panic!("Invalid api key when getting rates")
-        } else if status == requests::Status::LIMIT {
-            panic!("Exceeded API limit when getting rates")
-        } else if status == requests::Status::ERROR {
-            panic!("Unknown error when getting rates")
+    /// Name of provider rate comes from
+    pub source: String,
+    /// Date rate was effective for, set for historical conversions
+    pub date: Option<String>,
+    /// Currencies from input to target rate was derived through when there was no fresh direct
+    /// rate, empty for direct rate
+    pub path: Vec<String>,
+    /// Publication time of cached rate used because provider was unreachable, 0 if unknown
+    pub stale: Option<u64>,
+    /// Unix time provider published rate at, oldest one of derived rate, 0 if unknown
+    pub time_last_update: u64,
+    /// Unix time rate was fetched at, oldest one of derived rate, 0 if unknown
+    pub time_fetched: u64,
+}
+
+/// Cached rates of base currency, listed by [`list_rates`]
+pub struct RateList {
+    pub rates: Vec<(String, Decimal)>,
+    /// Publication time of cached rates used because provider was unreachable, 0 if unknown
+    pub stale: Option<u64>,
+}
+
+/// Rate with details of where it comes from
+struct ResolvedRate {
+    rate: Decimal,
+    source: String,
+    date: Option<String>,
+    path: Vec<String>,
+    stale: Option<u64>,
+    time_last_update: u64,
+    time_fetched: u64,
+}
+
+/// Time in seconds cached rates are fresh for after fetching, from `CURRENCY_TTL` env variable or
+/// set with `--set-ttl`. `None` if they are fresh until next update of provider
+pub fn get_ttl(cache: &Cache) -> Result<Option<u64>> {
+    let ttl = match var_os(config::TTL_ENV_NAME) {
+        Some(val) => Some(val.to_string_lossy().to_string()),
+        None => cache.get_config(config::TTL_CONFIG_NAME)?,
+    };
+    Ok(ttl
+        .and_then(|ttl| config::parse_duration(&ttl))
+        .filter(|ttl| *ttl > 0))
+}
+
+/// Time rates have to be fetched at or after to be fresh, from overridden maximum age and
+/// refresh, or TTL. `None` if freshness is decided by next update of provider
+fn get_fetched_since(cache: &Cache) -> Result<Option<u64>> {
+    let overrides = cache.overrides();
+    let now = config::get_current_time();
+    let since_max_age = overrides.max_age.map(|max_age| now.saturating_sub(max_age));
+    let since_refresh = overrides.refresh.then_some(cache.time_overridden());
+    if since_max_age.is_some() || since_refresh.is_some() {
+        return Ok(since_max_age.max(since_refresh));
+    }
+    Ok(get_ttl(cache)?.map(|ttl| now.saturating_sub(ttl)))
+}
+
+/// Whether rates fetched at `time_fetched` and published until `next_update` are fresh
+fn is_time_fresh(cache: &Cache, time_fetched: u64, next_update: u64) -> Result<bool> {
+    Ok(match get_fetched_since(cache)? {
+        Some(since) => time_fetched > 0 && time_fetched >= since,
+        None => next_update > config::get_current_time(),
+    })
+}
+
+/// Cached rates are fresh if they come from provider in chain and are not past next update, or
+/// if `--max-age` or TTL is set, were fetched recently enough
+fn is_fresh(cache: &Cache, code: &str) -> Result<bool> {
+    let (time_fetched, next_update) =
+        (cache.get_rates_fetch_time(code)?, cache.get_next_update(code)?);
+    Ok(is_time_fresh(cache, time_fetched, next_update)?
+        && providers::get_provider_names_with(cache)?.contains(&cache.get_rates_info(code)?.0))
+}
+
+/// Same as [`is_fresh`], but for single cached pair, `false` if pair isn't cached
+fn is_pair_fresh(cache: &Cache, code_from: &str, code_to: &str) -> Result<bool> {
+    if !cache.check_exchange(code_from, code_to)? {
+        return Ok(false);
+    }
+    let (provider, next_update) = cache.get_rate_info(code_from, code_to)?;
+    let time_fetched = cache.get_rate_fetch_time(code_from, code_to)?;
+    Ok(is_time_fresh(cache, time_fetched, next_update)?
+        && providers::get_provider_names_with(cache)?.contains(&provider))
+}
+
+/// Whether rate of pair, or all rates of `code_from` if `code_to` is `None`, have to be fetched
+/// from provider, either current ones or ones on `date`
+pub fn needs_fetch(
+    cache: &Cache,
+    code_from: &str,
+    code_to: Option<&str>,
+    date: Option<&str>,
+) -> Result<bool> {
+    let date = date.map(check_date).transpose()?;
+    Ok(match (code_to, date.as_deref()) {
+        (Some(code_to), Some(date)) => !cache.check_historical_exchange(code_from, code_to, date)?,
+        (None, Some(date)) => cache.list_historical_rates(code_from, date)?.is_empty(),
+        (Some(code_to), None) => {
+            !is_pair_fresh(cache, code_from, code_to)?
+                && !has_derived_rate(cache, code_from, code_to)?
+        }
+        (None, None) => !is_fresh(cache, code_from)?,
+    })
+}
+
+/// Name of provider cached rates with `code` as base come from
+pub fn get_source(cache: &Cache, code: &str) -> Result<String> {
+    Ok(providers::get_display_name(&cache.get_rates_info(code)?.0))
+}
+
+/// Checks if code is known, currencies of providers without API key are refreshed on miss
+pub fn check_code(cache: &Cache, code: &str) -> Result<bool> {
+    if cache.check_code(code)? {
+        return Ok(true);
+    }
+    if providers::requires_key_with(cache)? {
+        return Ok(false);
+    }
+    requests::get_chain_currencies(&providers::get_provider_names_with(cache)?)?;
+    Ok(cache.check_code(code)?)
+}
+
+/// Maximum age in seconds of cached rates used when provider is unreachable, overridden for
+/// `cache` or from `CURRENCY_MAX_STALE` env variable. Any age is allowed if not set
+pub fn get_max_stale(cache: &Cache) -> Option<u64> {
+    match cache.overrides().max_stale {
+        Some(secs) => Some(secs),
+        None => var_os(config::MAX_STALE_ENV_NAME)
+            .and_then(|val| config::parse_duration(&val.to_string_lossy())),
+    }
+}
+
+/// Lets cached rates of `code` published at `time` be used when fetching failed with `error`,
+/// as long as provider is unavailable and they are not older than [`get_max_stale`]
+fn use_stale(cache: &Cache, code: &str, time: u64, error: Error) -> Result<()> {
+    if !error.is_unavailable() {
+        return Err(error);
+    }
+    let age = config::get_current_time().saturating_sub(time);
+    match get_max_stale(cache) {
+        Some(max_stale) if time == 0 || age > max_stale => {
+            Err(Error::StaleRates(code.to_string(), Box::new(error)))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Warning shown when rates published at `time` are used because provider was unreachable
+pub fn stale_warning(time: u64) -> String {
+    let age = match time {
+        0 => "of unknown age".to_string(),
+        time => format!(
+            "published {} ago",
+            config::format_duration(config::get_current_time().saturating_sub(time))
+        ),
+    };
+    format!("Warning: provider is unreachable, using cached rates {}", age)
+}
+
+/// Fetches rates of `code` unless they are fresh, falling back to cached ones when provider is
+/// unreachable. Concurrent processes refreshing same base wait for first one and use rates it
+/// cached. Returns publication time of cached rates used because provider was unreachable, 0 if
+/// unknown
+pub fn update_rate(cache: &Cache, code: &str) -> Result<Option<u64>> {
+    if is_fresh(cache, code)? {
+        return Ok(None);
+    }
+    let result = cache::with_refresh_lock(code, || -> Result<()> {
+        if !is_fresh(cache, code)? {
+            requests::get_rates_from(&providers::get_provider_names_with(cache)?, code)?;
+        }
+        Ok(())
+    });
+    match result {
+        Err(e) if !cache.list_rates(code)?.is_empty() => {
+            let time = cache.get_rates_time(code)?;
+            use_stale(cache, code, time, e)?;
+            Ok(Some(time))
+        }
+        result => result.map(|_| None),
+    }
+}
+
+/// Base currencies refreshed by [`prefetch`], from `CURRENCY_WATCHLIST` env variable or set with
+/// `--set-watchlist`. Every known currency if not set
+pub fn get_watchlist(cache: &Cache) -> Result<Vec<String>> {
+    let watchlist = match var_os(config::WATCHLIST_ENV_NAME) {
+        Some(val) => Some(val.to_string_lossy().to_string()),
+        None => cache.get_config(config::WATCHLIST_CONFIG_NAME)?,
+    };
+    let codes: Vec<String> = watchlist
+        .unwrap_or_default()
+        .split(',')
+        .map(|code| code.trim().to_uppercase())
+        .filter(|code| !code.is_empty())
+        .collect();
+    if codes.is_empty() {
+        return Ok(cache.list_currencies()?.into_iter().map(|[code, _]| code).collect());
+    }
+    Ok(codes)
+}
+
+/// Outcome of [`prefetch`] for single base currency
+pub enum Prefetch {
+    /// Rates were fetched from provider with this name
+    Updated(String),
+    /// Cached rates are fresh, nothing was fetched
+    Fresh,
+    /// Not fetched, as provider said API quota is used up earlier in the run
+    QuotaExhausted,
+    Failed(Error),
+}
+
+/// Refreshes rates of each of `codes` that aren't fresh, so later conversions don't have to
+/// fetch. Whole rate tables are fetched regardless of fetch strategy. Once provider says API quota
+/// is used up, remaining currencies are skipped
+pub fn prefetch(cache: &Cache, codes: &[String]) -> Result<Vec<(String, Prefetch)>> {
+    let is_quota_reached = |e: &Error| match e {
+        Error::QuotaReached => true,
+        Error::AllProvidersFailed(errors) => {
+            errors.iter().all(|(_, e)| matches!(e, Error::QuotaReached))
+        }
+        _ => false,
+    };
+    let mut quota_reached = false;
+    let mut results: Vec<(String, Prefetch)> = Vec::new();
+    for code in codes {
+        let status = if !check_code(cache, code)? {
+            Prefetch::Failed(Error::UnknownCode(code.clone()))
+        } else if is_fresh(cache, code)? {
+            Prefetch::Fresh
+        } else if quota_reached {
+            Prefetch::QuotaExhausted
+        } else {
+            let result = cache::with_refresh_lock(code, || -> Result<Option<String>> {
+                if is_fresh(cache, code)? {
+                    return Ok(None);
+                }
+                let names = providers::get_provider_names_with(cache)?;
+                Ok(Some(requests::get_rates_from(&names, code)?))
+            });
+            match result {
+                Ok(Some(name)) => Prefetch::Updated(providers::get_display_name(&name)),
+                Ok(None) => Prefetch::Fresh,
+                Err(e) => {
+                    quota_reached = is_quota_reached(&e);
+                    Prefetch::Failed(e)
+                }
+            }
+        };
+        results.push((code.clone(), status));
+    }
+    Ok(results)
+}
+
+/// Maximum number of rates chained when deriving rate, from `CURRENCY_MAX_HOPS` env variable
+/// or set with `--set-max-hops`
+pub fn get_max_hops(cache: &Cache) -> Result<usize> {
+    let max_hops = match var_os(config::MAX_HOPS_ENV_NAME) {
+        Some(val) => Some(val.to_string_lossy().to_string()),
+        None => cache.get_config(config::MAX_HOPS_CONFIG_NAME)?,
+    };
+    Ok(max_hops
+        .and_then(|max_hops| max_hops.parse().ok())
+        .unwrap_or(config::DEFAULT_MAX_HOPS))
+}
+
+/// Rate derived by chaining cached rates
+pub struct RatePath {
+    pub rate: Decimal,
+    /// Currencies from input to target currency
+    pub path: Vec<String>,
+    /// Provider of each chained rate
+    pub providers: Vec<String>,
+    /// Cached rate each step uses as `[code_from, code_to]`, reversed for inverted rates
+    pub rates: Vec<[String; 2]>,
+}
+
+/// Fresh cached rates from providers in chain, as `[code_from, code_to, rate, provider]`
+fn chain_rates(cache: &Cache) -> Result<Vec<[String; 4]>> {
+    let names = providers::get_provider_names_with(cache)?;
+    let fetched_since = get_fetched_since(cache)?.map(|since| since.max(1));
+    let fresh_rates = cache.list_fresh_rates(config::get_current_time(), fetched_since)?;
+    Ok(fresh_rates
+        .into_iter()
+        .filter(|[_, _, _, provider]| names.contains(provider))
+        .collect())
+}
+
+/// Breadth first search for shortest chain of at most `max_hops` of `pairs`, which can be used
+/// inverted, but published ones are preferred on chains of same length. Returns index of pair
+/// each step uses and whether it's inverted
+fn search_chain(
+    pairs: &[(&str, &str)],
+    code_from: &str,
+    code_to: &str,
+    max_hops: usize,
+) -> Option<Vec<(usize, bool)>> {
+    let mut edges: HashMap<&str, Vec<(&str, usize, bool)>> = HashMap::new();
+    for (i, &(rate_from, rate_to)) in pairs.iter().enumerate() {
+        edges.entry(rate_from).or_default().push((rate_to, i, false));
+    }
+    for (i, &(rate_from, rate_to)) in pairs.iter().enumerate() {
+        edges.entry(rate_to).or_default().push((rate_from, i, true));
+    }
+
+    // Remembering edge each currency was reached by
+    let mut previous: HashMap<&str, (&str, usize, bool)> = HashMap::new();
+    let mut queue: VecDeque<(&str, usize)> = VecDeque::from([(code_from, 0)]);
+    while let Some((code, hops)) = queue.pop_front() {
+        if code == code_to {
+            break;
+        }
+        if hops == max_hops {
+            continue;
         }
+        for &(next, i, inverted) in edges.get(code).into_iter().flatten() {
+            if next != code_from && !previous.contains_key(next) {
+                previous.insert(next, (code, i, inverted));
+                queue.push_back((next, hops + 1));
+            }
+        }
+    }
+
+    let mut steps = Vec::new();
+    let mut code = code_to;
+    while code != code_from {
+        let &(prev, i, inverted) = previous.get(code)?;
+        steps.insert(0, (i, inverted));
+        code = prev;
+    }
+    Some(steps)
+}
+
+/// Shortest chain of at most `max_hops` fresh cached rates from providers in chain, so rates
+/// already known don't have to be fetched. Rates can be used inverted, but published ones are
+/// preferred on chains of same length
+pub fn find_rate_path(
+    cache: &Cache,
+    code_from: &str,
+    code_to: &str,
+    max_hops: usize,
+) -> Result<Option<RatePath>> {
+    if code_from == code_to {
+        return Ok(None);
+    }
+    let mut rates: Vec<(String, String, Decimal, String)> = Vec::new();
+    for [rate_from, rate_to, rate, provider] in chain_rates(cache)? {
+        // Unrelated malformed rate shouldn't stop the search
+        let Ok(rate) = parse_rate(&rate) else {
+            continue;
+        };
+        rates.push((rate_from, rate_to, rate, provider));
+    }
+    let pairs: Vec<(&str, &str)> = rates
+        .iter()
+        .map(|(rate_from, rate_to, _, _)| (rate_from.as_str(), rate_to.as_str()))
+        .collect();
+    let Some(steps) = search_chain(&pairs, code_from, code_to, max_hops) else {
+        return Ok(None);
+    };
+
+    let mut path = vec![code_from.to_string()];
+    let mut providers: Vec<String> = Vec::new();
+    let mut used_rates: Vec<[String; 2]> = Vec::new();
+    let mut rate = Decimal::ONE;
+    for (i, inverted) in steps {
+        let (rate_from, rate_to, edge_rate, provider) = &rates[i];
+        let (next, edge_rate) = match inverted {
+            false => (rate_to, *edge_rate),
+            true => (rate_from, Decimal::ONE / edge_rate),
+        };
+        rate *= edge_rate;
+        path.push(next.clone());
+        providers.push(provider.clone());
+        used_rates.push([rate_from.clone(), rate_to.clone()]);
+    }
+    Ok(Some(RatePath {
+        rate: rate.round_sf(8).unwrap_or_default().normalize(),
+        path,
+        providers,
+        rates: used_rates,
+    }))
+}
+
+/// Whether [`find_derived_rate`] would find rate, without computing it and looking up its
+/// sources
+fn has_derived_rate(cache: &Cache, code_from: &str, code_to: &str) -> Result<bool> {
+    if code_from == code_to {
+        return Ok(false);
+    }
+    let rates = chain_rates(cache)?;
+    let pairs: Vec<(&str, &str)> = rates
+        .iter()
+        .map(|[rate_from, rate_to, _, _]| (rate_from.as_str(), rate_to.as_str()))
+        .collect();
+    Ok(search_chain(&pairs, code_from, code_to, get_max_hops(cache)?).is_some())
+}
+
+/// Same as [`find_rate_path`] with configured maximum hops, with sources of rates combined
+fn find_derived_rate(
+    cache: &Cache,
+    code_from: &str,
+    code_to: &str,
+) -> Result<Option<ResolvedRate>> {
+    let Some(rate_path) = find_rate_path(cache, code_from, code_to, get_max_hops(cache)?)? else {
+        return Ok(None);
+    };
+    let mut sources: Vec<String> = Vec::new();
+    for provider in &rate_path.providers {
+        let source = providers::get_display_name(provider);
+        if !sources.contains(&source) {
+            sources.push(source);
+        }
+    }
+    // Derived rate is only as recent as oldest rate it's derived from
+    let (mut time_last_update, mut time_fetched) = (u64::MAX, u64::MAX);
+    for [rate_from, rate_to] in &rate_path.rates {
+        let provenance = cache.get_rate_provenance(rate_from, rate_to)?;
+        time_last_update = time_last_update.min(provenance.time_last_update);
+        time_fetched = time_fetched.min(provenance.time_fetched);
+    }
+    Ok(Some(ResolvedRate {
+        rate: rate_path.rate,
+        source: sources.join(", "),
+        date: None,
+        path: rate_path.path,
+        stale: None,
+        time_last_update,
+        time_fetched,
+    }))
+}
+
+/// Uses fresh cached rate, rate derived from other cached rates, or fetches rate in this order.
+/// When `refreshed` holds result of [`update_rate`] already run for `code_from`, cached rate is
+/// used instead of fetching again, as stale one if update fell back to cached rates
+fn resolve_rate(
+    cache: &Cache,
+    code_from: &str,
+    code_to: &str,
+    refreshed: Option<Option<u64>>,
+) -> Result<ResolvedRate> {
+    if !check_code(cache, code_from)? {
+        return Err(Error::UnknownCode(code_from.to_string()));
+    }
+    if !check_code(cache, code_to)? {
+        return Err(Error::UnknownCode(code_to.to_string()));
+    }
+    let mut stale = None;
+    if !is_pair_fresh(cache, code_from, code_to)? {
+        if let Some(derived_rate) = find_derived_rate(cache, code_from, code_to)? {
+            return Ok(derived_rate);
+        }
+        if let Some(stale) = refreshed {
+            return resolved_rate(cache, code_from, code_to, stale);
+        }
+        let result = cache::with_refresh_lock(code_from, || -> Result<()> {
+            if is_pair_fresh(cache, code_from, code_to)? {
+                return Ok(());
+            }
+            let names = providers::get_provider_names_with(cache)?;
+            match requests::get_fetch_strategy(cache)? {
+                FetchStrategy::Pair => requests::get_pair_from(&names, code_from, code_to)?,
+                FetchStrategy::Table => requests::get_rates_from(&names, code_from)?,
+            };
+            Ok(())
+        });
+        if let Err(e) = result {
+            if !cache.check_exchange(code_from, code_to)? {
+                return Err(e);
+            }
+            let time = cache.get_rate_time(code_from, code_to)?;
+            use_stale(cache, code_from, time, e)?;
+            stale = Some(time);
+        }
+    }
+    resolved_rate(cache, code_from, code_to, stale)
+}
+
+/// Cached rate of pair, or derived one if pair isn't cached
+fn resolved_rate(
+    cache: &Cache,
+    code_from: &str,
+    code_to: &str,
+    stale: Option<u64>,
+) -> Result<ResolvedRate> {
+    if !cache.check_exchange(code_from, code_to)? {
+        // Provider may not publish the pair, but fetched rates can complete a chain
+        return find_derived_rate(cache, code_from, code_to)?
+            .ok_or_else(|| Error::UnknownCode(code_to.to_string()));
+    }
+    let provenance = cache.get_rate_provenance(code_from, code_to)?;
+    Ok(ResolvedRate {
+        rate: parse_rate(&cache.get_rate(code_from, code_to)?)?,
+        source: providers::get_display_name(&provenance.provider),
+        date: None,
+        path: Vec::new(),
+        stale,
+        time_last_update: provenance.time_last_update,
+        time_fetched: provenance.time_fetched,
+    })
+}
+
+pub fn get_rate(cache: &Cache, code_from: &str, code_to: &str) -> Result<Decimal> {
+    Ok(resolve_rate(cache, code_from, code_to, None)?.rate)
+}
+
+pub fn list_rates(cache: &Cache, code: &str) -> Result<RateList> {
+    if !check_code(cache, code)? {
+        return Err(Error::UnknownCode(code.to_string()));
     }
+    let stale = update_rate(cache, code)?;
+    let rates = cache
+        .list_rates(code)?
+        .into_iter()
+        .map(|[code_to, rate]| Ok((code_to, parse_rate(&rate)?)))
+        .collect::<Result<_>>()?;
+    Ok(RateList { rates, stale })
 }
-pub fn get_rate(code_from: &String, code_to: &String) -> String {
-    if !cache::check_code(code_from).expect("Error on getting code status") {
-        panic!("Code {} doesn't exists, use correct code!", code_from);
+
+/// Checks that `date` is valid `YYYY-MM-DD` date and not in the future, returns it zero padded
+/// so it can be compared and used as cache key
+pub fn check_date(date: &str) -> Result<String> {
+    match config::parse_date(date) {
+        Some(time) if time <= config::get_current_time() => Ok(config::format_date(time)),
+        _ => Err(Error::InvalidDate(date.to_string())),
+    }
+}
+
+/// Rate as it was on `date`, cached rates of that date are never refreshed
+pub fn get_historical_rate(
+    cache: &Cache,
+    code_from: &str,
+    code_to: &str,
+    date: &str,
+) -> Result<Decimal> {
+    let date = &check_date(date)?;
+    if !check_code(cache, code_from)? {
+        return Err(Error::UnknownCode(code_from.to_string()));
     }
-    if !cache::check_code(code_to).expect("Error on getting code status") {
-        panic!("Code {} doesn't exists, use correct code!", code_to);
+    if !check_code(cache, code_to)? {
+        return Err(Error::UnknownCode(code_to.to_string()));
     }
-    if (!cache::check_exchange(code_from, code_to).expect("Error on getting exchange status"))
-        || (cache::get_next_update(code_from).expect("Error getting next update time from cache")
-            <= config::get_current_time())
-    {
-        update_rate(code_from);
+    if !cache.check_historical_exchange(code_from, code_to, date)? {
+        let names = providers::get_provider_names_with(cache)?;
+        requests::get_historical_rates_from(&names, code_from, date)?;
     }
-    cache::get_rate(code_from, code_to).expect("Error when getting cached rate")
+    if !cache.check_historical_exchange(code_from, code_to, date)? {
+        return Err(Error::UnknownCode(code_to.to_string()));
+    }
+    parse_rate(&cache.get_historical_rate(code_from, code_to, date)?.0)
 }
 
-pub fn convert_value(code_from: &String, code_to: &String, value: &String) -> Result{
-    if value.parse::<f64>().is_err() {
-        panic!("{} is not a number!", value);
+pub fn list_historical_rates(
+    cache: &Cache,
+    code: &str,
+    date: &str,
+) -> Result<Vec<(String, Decimal)>> {
+    let date = &check_date(date)?;
+    if !check_code(cache, code)? {
+        return Err(Error::UnknownCode(code.to_string()));
     }
-    let text_rate = get_rate(code_from, code_to);
-    let from_currency = find(code_from);
-    if from_currency.is_none() {
-        panic!("{} not found in ISO formats", code_from);
+    if cache.list_historical_rates(code, date)?.is_empty() {
+        let names = providers::get_provider_names_with(cache)?;
+        requests::get_historical_rates_from(&names, code, date)?;
     }
-    let to_currency = find(code_to);
-    if to_currency.is_none() {
-        panic!("{} not found in ISO formats", code_to);
+    cache.list_historical_rates(code, date)?
+        .into_iter()
+        .map(|[code_to, rate]| Ok((code_to, parse_rate(&rate)?)))
+        .collect()
+}
+
+/// Cached time series of pair as `(time, rate, source)`, oldest first, optionally only rates
+/// published since `since` date. Doesn't fetch anything
+pub fn rate_history(
+    cache: &Cache,
+    code_from: &str,
+    code_to: &str,
+    since: Option<&str>,
+) -> Result<Vec<(u64, Decimal, String)>> {
+    let since = match since {
+        Some(date) => config::parse_date(date).ok_or_else(|| Error::InvalidDate(date.to_string()))?,
+        None => 0,
+    };
+    for code in [code_from, code_to] {
+        if !cache.check_code(code)? {
+            return Err(Error::UnknownCode(code.to_string()));
+        }
     }
+    cache.get_rate_history(code_from, code_to, since)?
+        .into_iter()
+        .map(|(time, rate, provider)| {
+            Ok((time, parse_rate(&rate)?, providers::get_display_name(&provider)))
+        })
+        .collect()
+}
+
+/// Parses cached rate, caches from older versions may have rates in exponent notation
+fn parse_rate(rate: &str) -> Result<Decimal> {
+    Decimal::from_str(rate)
+        .or_else(|_| Decimal::from_scientific(rate))
+        .ok()
+        .filter(providers::is_valid_rate)
+        .ok_or_else(|| Error::MalformedResponse(format!("{} is not a valid rate", rate)))
+}
 
-    let rate = Decimal::from_str(&text_rate).unwrap();
-    let dec_amount = Decimal::from_str(&value).unwrap();
-    let from_money = Money::from_decimal(dec_amount, from_currency.unwrap());
-    let mut ret: Result = Result { from: String::new(), to: String::new(), rate: String::new()};
-    ret.from = from_money.to_string();
+pub fn convert_value(
+    cache: &Cache,
+    code_from: &str,
+    code_to: &str,
+    value: &str,
+) -> Result<Conversion> {
+    let dec_amount =
+        Decimal::from_str(value).map_err(|_| Error::InvalidAmount(value.to_string()))?;
+    convert(code_from, code_to, dec_amount, resolve_rate(cache, code_from, code_to, None)?)
+}
+
+/// Same as [`convert_value`] for each of `codes_to`. When more than one of them needs fetching,
+/// rate table of `code_from` is fetched once for all of them, regardless of fetch strategy
+pub fn convert_values(
+    cache: &Cache,
+    code_from: &str,
+    codes_to: &[String],
+    value: &str,
+) -> Result<Vec<Conversion>> {
+    let dec_amount =
+        Decimal::from_str(value).map_err(|_| Error::InvalidAmount(value.to_string()))?;
+    if !check_code(cache, code_from)? {
+        return Err(Error::UnknownCode(code_from.to_string()));
+    }
+    let mut to_fetch = 0;
+    for code_to in codes_to {
+        if check_code(cache, code_to)?
+            && !is_pair_fresh(cache, code_from, code_to)?
+            && !has_derived_rate(cache, code_from, code_to)?
+        {
+            to_fetch += 1;
+        }
+    }
+    let refreshed = match to_fetch {
+        0 | 1 => None,
+        _ => Some(update_rate(cache, code_from)?),
+    };
+    codes_to
+        .iter()
+        .map(|code_to| {
+            let rate = resolve_rate(cache, code_from, code_to, refreshed)?;
+            convert(code_from, code_to, dec_amount, rate)
+        })
+        .collect()
+}
+
+/// Same as [`convert_value`], but with rate as it was on `date` in `YYYY-MM-DD` format
+pub fn convert_value_at(
+    cache: &Cache,
+    code_from: &str,
+    code_to: &str,
+    value: &str,
+    date: &str,
+) -> Result<Conversion> {
+    let dec_amount =
+        Decimal::from_str(value).map_err(|_| Error::InvalidAmount(value.to_string()))?;
+    let date = &check_date(date)?;
+    let rate = get_historical_rate(cache, code_from, code_to, date)?;
+    let (_, provider, effective_date) = cache.get_historical_rate(code_from, code_to, date)?;
+    let provenance = cache.get_historical_rate_provenance(code_from, code_to, date)?;
+    let rate = ResolvedRate {
+        rate,
+        source: providers::get_display_name(&provider),
+        date: Some(effective_date.unwrap_or(date.to_string())),
+        path: Vec::new(),
+        stale: None,
+        time_last_update: provenance.time_last_update,
+        time_fetched: provenance.time_fetched,
+    };
+    convert(code_from, code_to, dec_amount, rate)
+}
+
+fn convert(
+    code_from: &str,
+    code_to: &str,
+    dec_amount: Decimal,
+    rate: ResolvedRate,
+) -> Result<Conversion> {
+    let ResolvedRate {
+        rate,
+        source,
+        date,
+        path,
+        stale,
+        time_last_update,
+        time_fetched,
+    } = rate;
+    let from_currency = find(code_from).ok_or_else(|| Error::NotIso(code_from.to_string()))?;
+    let to_currency = find(code_to).ok_or_else(|| Error::NotIso(code_to.to_string()))?;
+
+    let from_money = Money::from_decimal(dec_amount, from_currency);
+    let mut ret = Conversion {
+        code_from: code_from.to_string(),
+        code_to: code_to.to_string(),
+        amount: dec_amount,
+        value: dec_amount,
+        rate,
+        from: from_money.to_string(),
+        to: String::new(),
+        source,
+        date,
+        path,
+        stale,
+        time_last_update,
+        time_fetched,
+    };
     if code_from != code_to {
-        let ex = ExchangeRate::new(from_currency.unwrap(), to_currency.unwrap(), rate).unwrap();
-        let result = ex.convert(from_money).expect("Error while conversion");
+        // Money panics on overflow, so too large amount is rejected up front
+        if dec_amount.checked_mul(rate).is_none() {
+            return Err(Error::InvalidAmount(dec_amount.to_string()));
+        }
+        let ex = ExchangeRate::new(from_currency, to_currency, rate)
+            .expect("Exchange rate between different currencies");
+        let result = ex
+            .convert(from_money)
+            .expect("Money currency matches exchange rate");
+        ret.value = *result.amount();
         ret.to = result.to_string();
     } else {
         ret.to = from_money.to_string();
     }
-    ret.rate = text_rate;
-    ret
+    Ok(ret)
 }
 
-pub fn print_result(res:Result)
-{
+pub fn print_result(res: Conversion) {
     println!("Input: {}", res.from);
     println!("Equals: {}", res.to);
     println!("Exchange rate: {}", res.rate);
+    if !res.path.is_empty() {
+        println!("Derived through: {}", res.path.join(" -> "));
+    }
+    match res.time_last_update {
+        0 => println!("Source: {}", res.source),
+        time => println!(
+            "Rate as of {} UTC, source: {}",
+            config::format_time(time),
+            res.source
+        ),
+    }
+    if res.time_fetched > 0 {
+        println!("Fetched: {} UTC", config::format_time(res.time_fetched));
+    }
+    if let Some(date) = res.date {
+        println!("Rate date: {}", date);
+    }
+    if let Some(time) = res.stale {
+        println!("{}", stale_warning(time));
+    }
 }
@@ -0,0 +1,17 @@
+//! Currency conversion backed by a local SQLite cache of exchange rates.
+pub mod cache;
+#[macro_use]
+pub mod config;
+pub mod error;
+pub mod exchange;
+pub mod output;
+pub mod providers;
+pub mod requests;
+pub mod snapshot;
+#[cfg(test)]
+mod tests;
+
+pub use error::Error;
+pub use exchange::{
+    convert_value, convert_value_at, convert_values, get_rate, list_rates, Conversion, RateList,
+};
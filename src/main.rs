@@ -1,37 +1,82 @@
 use std::{io::Write, process::ExitCode};
 
-use crate::{
-    cache::{create_cache, set_api_key},
-    requests::get_currencies,
+use clap::{Parser, Subcommand};
+use currency_exchange::{
+    cache::{self, create_cache, set_api_key, Cache},
+    config, convert_value, convert_value_at, convert_values,
+    exchange::{self, print_result},
+    output::{self, Format},
+    providers,
+    requests::{self, get_chain_currencies},
+    snapshot,
+    Error,
 };
-use cache::check_code;
-use clap::Parser;
-use exchange::{convert_value, print_result};
-mod cache;
-#[macro_use]
-mod config;
-mod exchange;
-mod requests;
-#[cfg(test)]
-mod tests;
 
 #[derive(Parser)]
 #[command(about, long_about = None, arg_required_else_help = true)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Currency code to exchange from
     #[arg(value_names = ["Currency input"])]
     currency_from: Option<String>,
-    /// Currency code to exchange to
+    /// Currency code to exchange to, or comma separated codes
     #[arg(value_names = ["Currency target"])]
     currency_to: Option<String>,
     /// Currency amount to exchange
     #[arg(value_names = ["Amount"])]
     value: Option<String>,
+    /// Currency code to exchange to, can be repeated. Amount then follows currency input
+    #[arg(long = "to", value_names = ["CODE"], value_delimiter = ',', requires = "currency_from")]
+    to: Vec<String>,
 
     /// Set api key
     #[arg(short = 'k', long = "set-api-key")]
     api_key: Option<String>,
-    /// Recreate cache
+    /// Set rate providers, comma separated in priority order
+    #[arg(
+        short = 'p',
+        long = "set-provider",
+        value_delimiter = ',',
+        value_parser = providers::PROVIDER_NAMES
+    )]
+    set_provider: Vec<String>,
+    /// Set whether single pair or all rates of currency are fetched for conversion
+    #[arg(long = "set-fetch-strategy", value_parser = requests::FETCH_STRATEGY_NAMES)]
+    fetch_strategy: Option<String>,
+    /// Set maximum number of cached rates chained when there is no direct rate
+    #[arg(long = "set-max-hops", value_names = ["N"], value_parser = clap::value_parser!(u8))]
+    max_hops: Option<u8>,
+    /// Set how long fetched rates are used before refetching, like 6h, 0 to use them until
+    /// provider's next update
+    #[arg(long = "set-ttl", value_names = ["DURATION"], value_parser = parse_duration)]
+    ttl: Option<u64>,
+    /// Set currencies refreshed by --prefetch, comma separated, empty for all currencies
+    #[arg(long = "set-watchlist", value_names = ["CODES"], value_delimiter = ',')]
+    watchlist: Option<Vec<String>>,
+    /// Set CSV or JSON file used by file provider
+    #[arg(long = "set-rates-file", value_names = ["PATH"])]
+    rates_file: Option<std::path::PathBuf>,
+    /// Use rate providers for this run only, comma separated in priority order
+    #[arg(
+        short = 'P',
+        long = "provider",
+        value_delimiter = ',',
+        value_parser = providers::PROVIDER_NAMES
+    )]
+    provider: Vec<String>,
+    /// Refetch rates fetched longer ago than this for this run only, like 30m
+    #[arg(long = "max-age", value_names = ["DURATION"], value_parser = parse_duration)]
+    max_age: Option<u64>,
+    /// Refetch rates used in this run even if cached ones are fresh
+    #[arg(long, conflicts_with = "max_age")]
+    refresh: bool,
+    /// Fail instead of using older cached rates when provider is unreachable, like 36h or 2d
+    #[arg(long = "max-stale", value_names = ["DURATION"], value_parser = parse_duration)]
+    max_stale: Option<u64>,
+    /// Recreate cache, losing API key and cached rates
     #[arg(short = 'r', long = "recreate-cache")]
     recreate_cache: bool,
     /// Interactive mode
@@ -45,127 +90,513 @@ struct Cli {
     /// List exchange rate for currency
     #[arg(short = 'L', long = "list-rates", value_names = ["currency"])]
     list_rates: Option<String>,
+
+    /// List cached rates of pair over time, without fetching
+    #[arg(long = "history", num_args = 2, value_names = ["FROM", "TO"])]
+    history: Vec<String>,
+    /// Only list rates published since date with --history
+    #[arg(long = "since", value_names = ["YYYY-MM-DD"], requires = "history")]
+    since: Option<String>,
+
+    /// Same as `cache export`
+    #[arg(long = "export", value_names = ["PATH"])]
+    export: Option<std::path::PathBuf>,
+    /// Same as `cache import`
+    #[arg(long = "import", value_names = ["PATH"])]
+    import: Option<std::path::PathBuf>,
+
+    /// Refresh rates of watchlist currencies that aren't fresh, for running from cron
+    #[arg(long)]
+    prefetch: bool,
+
+    /// Show ExchangeRate-API quota usage
+    #[arg(long)]
+    quota: bool,
+
+    /// Output format, text is meant for people and can change between versions
+    #[arg(long, default_value = "text", value_parser = output::FORMAT_NAMES)]
+    format: String,
+
+    /// Use rates as they were on date, for conversion and --list-rates
+    #[arg(short = 'd', long = "date", value_names = ["YYYY-MM-DD"])]
+    date: Option<String>,
+}
+#[derive(Subcommand)]
+enum Command {
+    /// Move cached currencies and rates between machines
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+}
+#[derive(Subcommand)]
+enum CacheAction {
+    /// Export cached currencies and rates, as JSON for .json extension and CSV otherwise
+    Export {
+        /// Snapshot file to write
+        #[arg(value_names = ["PATH"])]
+        path: std::path::PathBuf,
+    },
+    /// Import cached currencies and rates exported with `cache export`, keeping newer rates
+    Import {
+        /// Snapshot file to merge into cache
+        #[arg(value_names = ["PATH"])]
+        path: std::path::PathBuf,
+    },
 }
-fn setup_key(key: String) -> Result<bool, Box<dyn std::error::Error>> {
+fn parse_duration(duration: &str) -> Result<u64, String> {
+    config::parse_duration(duration)
+        .ok_or_else(|| format!("{} is not a duration like 90m, 36h or 2d", duration))
+}
+fn setup_key(format: Format, key: String) -> Result<bool, Box<dyn std::error::Error>> {
     set_api_key(key)?;
-    let status = get_currencies()?;
-    if status == requests::Status::INVALID {
-        set_api_key("".to_string())?;
-        println!("Api Key is invalid");
-        return Ok(false);
-    } else if status == requests::Status::LIMIT {
-        set_api_key("".to_string())?;
-        println!("Can't set up API key due to exceeded API limit");
-        return Ok(false);
-    } else if status == requests::Status::ERROR {
-        set_api_key("".to_string())?;
-        println!("Can't set up API key due to unknown error");
-        return Ok(false);
-    }
-    Ok(true)
+    match requests::get_currencies_from(providers::exchangerate_api::ID) {
+        Ok(()) => return Ok(true),
+        Err(Error::InvalidKey) => warn(format, "Api Key is invalid"),
+        Err(Error::QuotaReached) => {
+            warn(format, "Can't set up API key due to exceeded API limit")
+        }
+        Err(e) => warn(format, &format!("Can't set up API key: {}", e)),
+    }
+    set_api_key("".to_string())?;
+    Ok(false)
+}
+
+/// Prints warning or confirmation, to stderr with machine-readable format so output stays
+/// parseable
+fn warn(format: Format, message: &str) {
+    match format {
+        Format::Text => println!("{}", message),
+        _ => eprintln!("{}", message),
+    }
+}
+
+/// Prints records in machine-readable `format`, returns `false` for text format
+fn print_records<T: output::Record>(format: Format, records: &[T]) -> bool {
+    match output::format_records(format, records) {
+        Some(text) => print!("{}", ensure_newline(text)),
+        None => return false,
+    }
+    true
 }
 
-fn main() -> Result<ExitCode, Box<dyn std::error::Error>> {
-    let args = Cli::parse();
-    let all_args =
-        args.currency_from.is_some() && args.currency_to.is_some() && args.value.is_some();
+fn ensure_newline(mut text: String) -> String {
+    if !text.ends_with('\n') {
+        text.push('\n');
+    }
+    text
+}
+
+/// Warns when fetch is needed and cached ExchangeRate-API quota is running low
+fn warn_quota(
+    format: Format,
+    cache: &Cache,
+    needs_fetch: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let names = providers::get_provider_names_with(cache)?;
+    if !needs_fetch || !names.contains(&providers::exchangerate_api::ID.to_string()) {
+        return Ok(());
+    }
+    if let Some(quota) = requests::get_cached_quota()? {
+        if quota.is_low() {
+            let message = format!(
+                "Warning: only {} of {} ExchangeRate-API requests left, quota refreshes on day {}",
+                quota.requests_remaining, quota.plan_quota, quota.refresh_day_of_month
+            );
+            warn(format, &message);
+        }
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let mut args = Cli::parse();
+    let format = Format::from_name(&args.format);
+    // Cache subcommands are handled same as --export and --import flags
+    match args.command.take() {
+        Some(Command::Cache { action: CacheAction::Export { path } }) => args.export = Some(path),
+        Some(Command::Cache { action: CacheAction::Import { path } }) => args.import = Some(path),
+        None => {}
+    }
+    // With --to, amount directly follows currency input
+    if !args.to.is_empty() && args.value.is_none() {
+        args.value = args.currency_to.take();
+    }
+    let codes_to: Vec<String> = args
+        .currency_to
+        .iter()
+        .flat_map(|codes| codes.split(','))
+        .chain(args.to.iter().map(String::as_str))
+        .map(|code| code.trim().to_uppercase())
+        .filter(|code| !code.is_empty())
+        .collect();
+    let all_args = args.currency_from.is_some() && !codes_to.is_empty() && args.value.is_some();
     let wrong_args =
-        args.currency_from.is_some() && (args.currency_to.is_none() || args.value.is_none());
+        args.currency_from.is_some() && (codes_to.is_empty() || args.value.is_none());
     // Checks
     if args.interactive && (all_args || wrong_args) {
-        println!("Do not provide codes and value with --interactive");
+        warn(format, "Do not provide codes and value with --interactive");
         return Ok(ExitCode::FAILURE);
     }
-    if args.interactive && (args.list || args.list_rates.is_some()) {
-        println!("Can't use --list or --list-rates with --interactive");
+    let actions = [
+        args.list,
+        args.list_rates.is_some(),
+        args.quota,
+        !args.history.is_empty(),
+        args.prefetch,
+    ];
+    if args.interactive && actions.contains(&true) {
+        warn(
+            format,
+            "Can't use --list, --list-rates, --history, --prefetch or --quota with --interactive",
+        );
         return Ok(ExitCode::FAILURE);
     }
-    if args.currency_from.is_some() && (args.list || args.list_rates.is_some()) {
-        println!("Can't use --list or --list-rates while providing exchange data");
+    if args.currency_from.is_some() && actions.contains(&true) {
+        warn(
+            format,
+            "Can't use --list, --list-rates, --history, --prefetch or --quota while providing \
+             exchange data",
+        );
         return Ok(ExitCode::FAILURE);
     }
-    if args.list && args.list_rates.is_some(){
-        println!("Can't use --list with --list-rates");
+    let no_date = args.interactive || args.list || !args.history.is_empty() || args.prefetch;
+    if args.date.is_some() && no_date {
+        warn(format, "Can't use --date with --interactive, --list, --history or --prefetch");
+        return Ok(ExitCode::FAILURE);
+    }
+    if args.interactive && format != Format::Text {
+        warn(format, "Can't use --format other than text with --interactive");
+        return Ok(ExitCode::FAILURE);
+    }
+    if actions.iter().filter(|x| **x).count() > 1 {
+        warn(format, "Can't use --list, --list-rates, --history, --prefetch and --quota together");
         return Ok(ExitCode::FAILURE);
     }
     if config::get_cache_path().is_dir()
     {
-        println!("Specified path cache path is dir, not file");
+        warn(format, "Specified path cache path is dir, not file");
         return Ok(ExitCode::FAILURE);
     }
     // Create cache if arg provided or doesn't exist
-    if args.recreate_cache || !config::get_cache_path().exists() {
+    if args.recreate_cache {
         create_cache()?;
-        println!("New cache has been created");
+        warn(format, "New cache has been created");
+    } else if !config::get_cache_path().exists() {
+        cache::init_cache()?;
+        warn(format, "New cache has been created");
+    }
+    // Set provider if arg provided
+    if !args.set_provider.is_empty() {
+        let names = args.set_provider.join(",");
+        cache::set_config(config::PROVIDER_CONFIG_NAME, &names)?;
+        warn(format, &format!("Provider set to {}", names));
+    }
+    let overrides = config::Overrides {
+        providers: (!args.provider.is_empty()).then_some(args.provider),
+        max_age: args.max_age,
+        refresh: args.refresh,
+        max_stale: args.max_stale,
+    };
+    if let Some(strategy) = args.fetch_strategy {
+        cache::set_config(config::FETCH_STRATEGY_CONFIG_NAME, &strategy)?;
+        warn(format, &format!("Fetch strategy set to {}", strategy));
     }
+    if let Some(max_hops) = args.max_hops {
+        cache::set_config(config::MAX_HOPS_CONFIG_NAME, &max_hops.to_string())?;
+        warn(format, &format!("Maximum hops set to {}", max_hops));
+    }
+    if let Some(ttl) = args.ttl {
+        cache::set_config(config::TTL_CONFIG_NAME, &ttl.to_string())?;
+        match ttl {
+            0 => warn(format, "TTL unset, rates are used until provider's next update"),
+            ttl => warn(format, &format!("TTL set to {}", config::format_duration(ttl))),
+        }
+    }
+    if let Some(watchlist) = args.watchlist {
+        let codes: Vec<String> = watchlist
+            .iter()
+            .map(|code| code.trim().to_uppercase())
+            .filter(|code| !code.is_empty())
+            .collect();
+        cache::set_config(config::WATCHLIST_CONFIG_NAME, &codes.join(","))?;
+        match codes.is_empty() {
+            true => warn(format, "Watchlist unset, --prefetch refreshes all currencies"),
+            false => warn(format, &format!("Watchlist set to {}", codes.join(","))),
+        }
+    }
+    // Validate and set rates file if arg provided
+    if let Some(path) = args.rates_file {
+        let path = std::fs::canonicalize(&path).unwrap_or(path);
+        let rates = providers::FileProvider::new(path.clone()).read()?;
+        cache::set_config(config::RATES_FILE_CONFIG_NAME, &path.to_string_lossy())?;
+        let message = format!("Rates file set to {} ({} rates)", path.display(), rates.len());
+        warn(format, &message);
+    }
+    let cache = Cache::open_with(overrides)?;
+    let requires_key = providers::requires_key_with(&cache)?;
     // Set up api key if arg provided
-    match args.api_key {
-        None => {}
-        Some(key) => {
-            let res = setup_key(key)
-                .expect("Unknown error while setting up key");
-            if !res {
-                return Ok(ExitCode::FAILURE);
-            }
+    if let Some(key) = args.api_key {
+        if !setup_key(format, key)? {
+            return Ok(ExitCode::FAILURE);
         }
     }
 
+    // Import before export, so both can be used to merge snapshots
+    if let Some(path) = &args.import {
+        let (currencies, rates) = snapshot::import(path)?;
+        let message = format!(
+            "Imported {} currencies and {} rates from {}",
+            currencies,
+            rates,
+            path.display()
+        );
+        warn(format, &message);
+    }
+    if let Some(path) = &args.export {
+        let exported = snapshot::export(path)?;
+        let message = format!(
+            "Exported {} currencies and {} rates to {}",
+            exported.currencies.len(),
+            exported.exchange_rates.len(),
+            path.display()
+        );
+        warn(format, &message);
+    }
+    // Snapshots are meant for machines without API access, so nothing else is needed
+    let other_action = args.interactive || args.currency_from.is_some() || actions.contains(&true);
+    if !other_action && (args.import.is_some() || args.export.is_some()) {
+        return Ok(ExitCode::SUCCESS);
+    }
+    // Show quota with --quota, only ExchangeRate-API key is needed
+    if args.quota {
+        let quota = requests::get_quota()?;
+        let record = output::QuotaRecord::from(&quota);
+        if let Some(text) = output::format_record(format, &record) {
+            print!("{}", ensure_newline(text));
+            return Ok(ExitCode::SUCCESS);
+        }
+        println!("Monthly quota: {}", quota.plan_quota);
+        println!("Requests remaining: {}", quota.requests_remaining);
+        println!("Quota refreshes on day {} of month", quota.refresh_day_of_month);
+        return Ok(ExitCode::SUCCESS);
+    }
     // Not interactive mode, based on provided arguments
     if !args.interactive {
-        // Check if api key is in cache
-        if !(cache::get_api_key()
-            .expect("Error while getting api key")
-            .len()
-            > 0)
-        {
-            
-            println!("API Key is not set up!");
+        // Check if api key is in cache, imported snapshot can be used without it
+        let no_currencies = cache::list_currencies()?.is_empty();
+        if requires_key && no_currencies && cache::get_api_key()?.is_empty() {
+            warn(format, "API Key is not set up!");
             return Ok(ExitCode::FAILURE);
         }
+        // Providers without api key don't get currencies fetched on key setup
+        if no_currencies {
+            get_chain_currencies(&providers::get_provider_names_with(&cache)?)?;
+        }
+        let date = args.date.as_deref().map(exchange::check_date).transpose()?;
+        let date = date.as_deref();
         // List currencies with --list
         if args.list {
-            let currencies = cache::list_currencies()?;
-            for currency in currencies {
-                println!("{} - {}", currency[0], currency[1]);
-            }        
+            let currencies = cache.list_currencies()?;
+            let records: Vec<output::CurrencyRecord> = currencies
+                .iter()
+                .map(|[code, name]| output::CurrencyRecord {
+                    code: code.clone(),
+                    name: name.clone(),
+                })
+                .collect();
+            if !print_records(format, &records) {
+                for currency in currencies {
+                    println!("{} - {}", currency[0], currency[1]);
+                }
+            }
         } 
         // List rates for currency with --list-rates <code>
         else if args.list_rates.is_some() {
             let code = args.list_rates.unwrap().clone();
-            let check = check_code(&code)?;
+            let check = exchange::check_code(&cache, &code)?;
             if !check {
-                println!("Code {} not found", code);
+                warn(format, &format!("Code {} not found", code));
                 return Ok(ExitCode::FAILURE);
             }
-            exchange::update_rate(&code);
-            let rates = cache::list_rates(&code)?;
-            for rate in rates {
-                println!("{} to {} rate: {}", code, rate[0], rate[1]);
+            let needs_fetch = exchange::needs_fetch(&cache, &code, None, date)?;
+            warn_quota(format, &cache, needs_fetch)?;
+            let text = format == Format::Text;
+            let rates = if let Some(date) = date {
+                let rates = exchange::list_historical_rates(&cache, &code, date)?;
+                if let (true, Some((code_to, _))) = (text, rates.first()) {
+                    let (_, provider, effective_date) =
+                        cache.get_historical_rate(&code, code_to, date)?;
+                    println!("Source: {}", providers::get_display_name(&provider));
+                    println!("Rates effective {}", effective_date.as_deref().unwrap_or(date));
+                }
+                rates
+            } else {
+                let exchange::RateList { rates, stale } = exchange::list_rates(&cache, &code)?;
+                let source = exchange::get_source(&cache, &code)?;
+                match cache.get_rates_time(&code)? {
+                    _ if !text => {}
+                    0 => println!("Source: {}", source),
+                    time => {
+                        let time = config::format_time(time);
+                        println!("Rates as of {} UTC, source: {}", time, source);
+                    }
+                }
+                if let Some(time) = stale {
+                    warn(format, &exchange::stale_warning(time));
+                }
+                if let (true, (_, Some(table_no), Some(effective_date))) =
+                    (text, cache.get_rates_info(&code)?)
+                {
+                    println!("Table {} effective {}", table_no, effective_date);
+                }
+                rates
+            };
+            let code = code.to_uppercase();
+            let records: Vec<output::RateRecord> = rates
+                .iter()
+                .map(|(code_to, rate)| output::RateRecord {
+                    code_from: code.clone(),
+                    code_to: code_to.clone(),
+                    rate: rate.to_string(),
+                })
+                .collect();
+            if !print_records(format, &records) {
+                for (code_to, rate) in rates {
+                    println!("{} to {} rate: {}", code, code_to, rate);
+                }
             }
         } 
+        // Refresh stale rates of watchlist with --prefetch
+        else if args.prefetch {
+            let (mut updated, mut skipped, mut failed) = (0, 0, 0);
+            let codes = exchange::get_watchlist(&cache)?;
+            let results = exchange::prefetch(&cache, &codes)?;
+            let records: Vec<output::PrefetchRecord> = results
+                .iter()
+                .map(|(code, status)| output::PrefetchRecord::new(code, status))
+                .collect();
+            if print_records(format, &records) {
+                let failed = records.iter().any(|record| record.status == "failed");
+                return Ok(if failed { ExitCode::FAILURE } else { ExitCode::SUCCESS });
+            }
+            for (code, status) in results {
+                match status {
+                    exchange::Prefetch::Updated(source) => {
+                        updated += 1;
+                        println!("{}: updated from {}", code, source);
+                    }
+                    exchange::Prefetch::Fresh => {
+                        skipped += 1;
+                        println!("{}: skipped, rates are fresh", code);
+                    }
+                    exchange::Prefetch::QuotaExhausted => {
+                        skipped += 1;
+                        println!("{}: skipped, API quota is used up", code);
+                    }
+                    exchange::Prefetch::Failed(e) => {
+                        failed += 1;
+                        println!("{}: failed, {}", code, e);
+                    }
+                }
+            }
+            println!("Updated {}, skipped {}, failed {}", updated, skipped, failed);
+            if failed > 0 {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        // List cached rates of pair with --history <from> <to>
+        else if let [code_from, code_to] = &args.history[..] {
+            let (code_from, code_to) = (code_from.to_uppercase(), code_to.to_uppercase());
+            let since = args.since.as_deref();
+            let history = exchange::rate_history(&cache, &code_from, &code_to, since)?;
+            let records: Vec<output::HistoryRecord> = history
+                .iter()
+                .map(|(time, rate, source)| output::HistoryRecord {
+                    time: *time,
+                    code_from: code_from.clone(),
+                    code_to: code_to.clone(),
+                    rate: rate.to_string(),
+                    source: source.clone(),
+                })
+                .collect();
+            if print_records(format, &records) {
+                return Ok(ExitCode::SUCCESS);
+            }
+            if history.is_empty() {
+                println!("No cached rates of {} to {}", code_from, code_to);
+            }
+            for (time, rate, source) in history {
+                println!(
+                    "{} UTC {} to {} rate: {} ({})",
+                    config::format_time(time),
+                    code_from,
+                    code_to,
+                    rate,
+                    source
+                );
+            }
+        }
         // Check if all 3 args are provided
         else if wrong_args {
-            println!("Not all args specified, provide 'currency from', 'currency to' and 'amount'");
+            let message =
+                "Not all args specified, provide 'currency from', 'currency to' and 'amount'";
+            warn(format, message);
             return Ok(ExitCode::FAILURE);
         } 
         // Do conversion
         else if all_args {
-            print_result(convert_value(
-                &args.currency_from.unwrap().to_uppercase(),
-                &args.currency_to.unwrap().to_uppercase(),
-                &args.value.unwrap(),
-            ))
+            let code_from = args.currency_from.unwrap().to_uppercase();
+            let value = args.value.unwrap();
+            let mut needs_fetch = false;
+            for code_to in &codes_to {
+                needs_fetch |= exchange::needs_fetch(&cache, &code_from, Some(code_to), date)?;
+            }
+            warn_quota(format, &cache, needs_fetch)?;
+            let conversions = match date {
+                Some(date) => codes_to
+                    .iter()
+                    .map(|code_to| convert_value_at(&cache, &code_from, code_to, &value, date))
+                    .collect::<Result<Vec<_>, _>>()?,
+                None => convert_values(&cache, &code_from, &codes_to, &value)?,
+            };
+            let records: Vec<output::ConversionRecord> =
+                conversions.iter().map(output::ConversionRecord::from).collect();
+            // Array even for single target, so shape doesn't depend on number of targets
+            match output::format_records(format, &records) {
+                Some(text) => {
+                    if let Some(time) = conversions.iter().find_map(|conversion| conversion.stale) {
+                        warn(format, &exchange::stale_warning(time));
+                    }
+                    print!("{}", ensure_newline(text));
+                }
+                None => {
+                    for (i, conversion) in conversions.into_iter().enumerate() {
+                        if i > 0 {
+                            println!();
+                        }
+                        print_result(conversion);
+                    }
+                }
+            }
         }
     } else {
-        interactive()?;
+        interactive(&cache, requires_key)?;
     }
     Ok(ExitCode::SUCCESS)
 }
-fn interactive() -> Result<(), Box<dyn std::error::Error>> {
-    let mut key_setup = cache::get_api_key()
-        .expect("Error while getting api key")
-        .len()
-        > 0;
+fn interactive(cache: &Cache, requires_key: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut key_setup = !requires_key || !cache::get_api_key()?.is_empty();
     while !key_setup {
         let mut key_string = String::new();
         print!("Please enter API Key: ");
@@ -173,12 +604,10 @@ fn interactive() -> Result<(), Box<dyn std::error::Error>> {
         std::io::stdin()
             .read_line(&mut key_string)
             .expect("Did not enter a correct string");
-        setup_key(key_string.trim().to_string())
-            .expect("Unknown error while setting up key");
-        key_setup = cache::get_api_key()
-            .expect("Error while getting api key")
-            .len()
-            > 0;
+        key_setup = setup_key(Format::Text, key_string.trim().to_string())?;
+    }
+    if cache::list_currencies()?.is_empty() {
+        get_chain_currencies(&providers::get_provider_names_with(cache)?)?;
     }
 
     let mut code_from: String = String::new();
@@ -197,7 +626,7 @@ fn interactive() -> Result<(), Box<dyn std::error::Error>> {
             .read_line(&mut code_from)
             .expect("Did not enter a correct string");
         code_from = code_from.trim().to_uppercase().to_string();
-        code_from_check = cache::check_code(&code_from)?;
+        code_from_check = exchange::check_code(cache, &code_from)?;
         if !code_from_check {
             println!("Code {} is unknown", code_from);
         }
@@ -210,7 +639,7 @@ fn interactive() -> Result<(), Box<dyn std::error::Error>> {
             .read_line(&mut code_to)
             .expect("Did not enter a correct string");
         code_to = code_to.trim().to_uppercase().to_string();
-        code_to_check = cache::check_code(&code_to)?;
+        code_to_check = exchange::check_code(cache, &code_to)?;
         if !code_to_check {
             println!("Code {} is unknown", code_to);
         }
@@ -224,13 +653,15 @@ fn interactive() -> Result<(), Box<dyn std::error::Error>> {
             .read_line(&mut amount)
             .expect("Did not enter a correct string");
         amount = amount.trim().to_string();
-        if amount.parse::<f64>().is_err() {
+        if amount.parse::<rust_decimal::Decimal>().is_err() {
             println!("{} is not a number!", amount)
         } else {
             amount_check = true
         }
     }
-    print_result(convert_value(&code_from, &code_to, &amount));
+    let needs_fetch = exchange::needs_fetch(cache, &code_from, Some(&code_to), None)?;
+    warn_quota(Format::Text, cache, needs_fetch)?;
+    print_result(convert_value(cache, &code_from, &code_to, &amount)?);
 
     Ok(())
 }
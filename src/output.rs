@@ -0,0 +1,277 @@
+//! Machine-readable output selected with `--format`. Numbers are exact decimal strings, times are
+//! unix times and missing values are `null` in JSON and empty in CSV and TSV
+use serde::Serialize;
+
+use crate::exchange::{Conversion, Prefetch};
+use crate::providers::exchangerate_api::Quota;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// Lines meant for people, not stable between versions
+    Text,
+    Json,
+    Csv,
+    /// Same as CSV, but tab separated
+    Tsv,
+}
+
+pub const FORMAT_NAMES: [&str; 4] = ["text", "json", "csv", "tsv"];
+
+impl Format {
+    pub fn from_name(name: &str) -> Format {
+        match name {
+            "json" => Format::Json,
+            "csv" => Format::Csv,
+            "tsv" => Format::Tsv,
+            _ => Format::Text,
+        }
+    }
+}
+
+/// Row of output, serialized as JSON object or as CSV row under [`Record::HEADER`]
+pub trait Record: Serialize {
+    const HEADER: &'static [&'static str];
+    fn row(&self) -> Vec<String>;
+}
+
+fn optional<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map(ToString::to_string).unwrap_or_default()
+}
+
+/// Treats 0 used for unknown times as missing
+fn known(time: u64) -> Option<u64> {
+    (time > 0).then_some(time)
+}
+
+#[derive(Serialize)]
+pub struct ConversionRecord {
+    pub code_from: String,
+    pub code_to: String,
+    pub amount: String,
+    pub value: String,
+    pub rate: String,
+    /// Input amount formatted as money
+    pub from: String,
+    /// Converted amount formatted as money
+    pub to: String,
+    pub source: String,
+    /// Date rate was effective for, for historical conversions
+    pub date: Option<String>,
+    /// Currencies rate was derived through, empty for direct rate. Space separated in CSV
+    pub path: Vec<String>,
+    pub time_last_update: Option<u64>,
+    pub time_fetched: Option<u64>,
+    /// Whether cached rate past its next update was used because provider was unreachable
+    pub stale: bool,
+}
+
+impl From<&Conversion> for ConversionRecord {
+    fn from(conversion: &Conversion) -> ConversionRecord {
+        ConversionRecord {
+            code_from: conversion.code_from.clone(),
+            code_to: conversion.code_to.clone(),
+            amount: conversion.amount.to_string(),
+            value: conversion.value.to_string(),
+            rate: conversion.rate.to_string(),
+            from: conversion.from.clone(),
+            to: conversion.to.clone(),
+            source: conversion.source.clone(),
+            date: conversion.date.clone(),
+            path: conversion.path.clone(),
+            time_last_update: known(conversion.time_last_update),
+            time_fetched: known(conversion.time_fetched),
+            stale: conversion.stale.is_some(),
+        }
+    }
+}
+
+impl Record for ConversionRecord {
+    const HEADER: &'static [&'static str] = &[
+        "code_from",
+        "code_to",
+        "amount",
+        "value",
+        "rate",
+        "from",
+        "to",
+        "source",
+        "date",
+        "path",
+        "time_last_update",
+        "time_fetched",
+        "stale",
+    ];
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.code_from.clone(),
+            self.code_to.clone(),
+            self.amount.clone(),
+            self.value.clone(),
+            self.rate.clone(),
+            self.from.clone(),
+            self.to.clone(),
+            self.source.clone(),
+            optional(&self.date),
+            self.path.join(" "),
+            optional(&self.time_last_update),
+            optional(&self.time_fetched),
+            self.stale.to_string(),
+        ]
+    }
+}
+
+#[derive(Serialize)]
+pub struct CurrencyRecord {
+    pub code: String,
+    pub name: String,
+}
+
+impl Record for CurrencyRecord {
+    const HEADER: &'static [&'static str] = &["code", "name"];
+    fn row(&self) -> Vec<String> {
+        vec![self.code.clone(), self.name.clone()]
+    }
+}
+
+#[derive(Serialize)]
+pub struct RateRecord {
+    pub code_from: String,
+    pub code_to: String,
+    pub rate: String,
+}
+
+impl Record for RateRecord {
+    const HEADER: &'static [&'static str] = &["code_from", "code_to", "rate"];
+    fn row(&self) -> Vec<String> {
+        vec![self.code_from.clone(), self.code_to.clone(), self.rate.clone()]
+    }
+}
+
+/// Rate of `--history`
+#[derive(Serialize)]
+pub struct HistoryRecord {
+    /// Unix time rate was published at
+    pub time: u64,
+    pub code_from: String,
+    pub code_to: String,
+    pub rate: String,
+    pub source: String,
+}
+
+impl Record for HistoryRecord {
+    const HEADER: &'static [&'static str] = &["time", "code_from", "code_to", "rate", "source"];
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.time.to_string(),
+            self.code_from.clone(),
+            self.code_to.clone(),
+            self.rate.clone(),
+            self.source.clone(),
+        ]
+    }
+}
+
+/// Outcome of `--prefetch` for single currency
+#[derive(Serialize)]
+pub struct PrefetchRecord {
+    pub code: String,
+    /// `updated`, `fresh`, `quota_exhausted` or `failed`
+    pub status: String,
+    /// Provider rates were fetched from when updated
+    pub source: Option<String>,
+    /// Why fetching failed
+    pub error: Option<String>,
+}
+
+impl PrefetchRecord {
+    pub fn new(code: &str, prefetch: &Prefetch) -> PrefetchRecord {
+        let (status, source, error) = match prefetch {
+            Prefetch::Updated(source) => ("updated", Some(source.clone()), None),
+            Prefetch::Fresh => ("fresh", None, None),
+            Prefetch::QuotaExhausted => ("quota_exhausted", None, None),
+            Prefetch::Failed(e) => ("failed", None, Some(e.to_string())),
+        };
+        PrefetchRecord {
+            code: code.to_string(),
+            status: status.to_string(),
+            source,
+            error,
+        }
+    }
+}
+
+impl Record for PrefetchRecord {
+    const HEADER: &'static [&'static str] = &["code", "status", "source", "error"];
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.code.clone(),
+            self.status.clone(),
+            optional(&self.source),
+            optional(&self.error),
+        ]
+    }
+}
+
+/// ExchangeRate-API quota of `--quota`
+#[derive(Serialize)]
+pub struct QuotaRecord {
+    pub plan_quota: u64,
+    pub requests_remaining: u64,
+    pub refresh_day_of_month: u32,
+}
+
+impl From<&Quota> for QuotaRecord {
+    fn from(quota: &Quota) -> QuotaRecord {
+        QuotaRecord {
+            plan_quota: quota.plan_quota,
+            requests_remaining: quota.requests_remaining,
+            refresh_day_of_month: quota.refresh_day_of_month,
+        }
+    }
+}
+
+impl Record for QuotaRecord {
+    const HEADER: &'static [&'static str] =
+        &["plan_quota", "requests_remaining", "refresh_day_of_month"];
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.plan_quota.to_string(),
+            self.requests_remaining.to_string(),
+            self.refresh_day_of_month.to_string(),
+        ]
+    }
+}
+
+fn to_delimited<T: Record>(records: &[T], delimiter: u8) -> String {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(Vec::new());
+    writer.write_record(T::HEADER).expect("Writing to memory can't fail");
+    for record in records {
+        writer.write_record(record.row()).expect("Writing to memory can't fail");
+    }
+    let bytes = writer.into_inner().expect("Writing to memory can't fail");
+    String::from_utf8(bytes).expect("Records are valid UTF-8")
+}
+
+/// Records as JSON array or as CSV or TSV with header, `None` for [`Format::Text`]
+pub fn format_records<T: Record>(format: Format, records: &[T]) -> Option<String> {
+    match format {
+        Format::Text => None,
+        Format::Json => {
+            Some(serde_json::to_string_pretty(records).expect("Records serialize to JSON"))
+        }
+        Format::Csv => Some(to_delimited(records, b',')),
+        Format::Tsv => Some(to_delimited(records, b'\t')),
+    }
+}
+
+/// Same as [`format_records`], but single record is JSON object instead of array
+pub fn format_record<T: Record>(format: Format, record: &T) -> Option<String> {
+    match format {
+        Format::Json => {
+            Some(serde_json::to_string_pretty(record).expect("Records serialize to JSON"))
+        }
+        format => format_records(format, std::slice::from_ref(record)),
+    }
+}
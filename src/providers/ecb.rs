@@ -0,0 +1,172 @@
+use std::{collections::HashMap, str::FromStr};
+
+use rust_decimal::Decimal;
+use rusty_money::iso::find;
+
+use super::{is_valid_rate, next_working_day, Provider, Rates};
+use crate::config;
+use crate::error::{Error, Result};
+
+pub const ID: &str = "ecb";
+pub const NAME: &str = "European Central Bank";
+
+/// ECB publishes reference rates around 16:00 CET
+const PUBLICATION_TIME: u64 = 15 * 3600;
+
+/// Reference rates of single day, EUR based
+pub struct ReferenceRates {
+    /// Date in `YYYY-MM-DD` format
+    pub date: String,
+    pub rates: HashMap<String, Decimal>,
+}
+
+impl ReferenceRates {
+    /// Rates with `code` as base, derived by cross-calculation for currencies other than EUR
+    pub fn rebase(&self, code: &str) -> Result<HashMap<String, Decimal>> {
+        let base = self
+            .rates
+            .get(code)
+            .ok_or_else(|| Error::UnknownCode(code.to_string()))?;
+        Ok(self
+            .rates
+            .iter()
+            .map(|(code_to, rate)| {
+                let cross = if code_to == code {
+                    Decimal::ONE
+                } else {
+                    (rate / base).round_sf(8).unwrap_or_default().normalize()
+                };
+                (code_to.clone(), cross)
+            })
+            .collect())
+    }
+}
+
+/// Parses `eurofxref-daily.xml` or `eurofxref-hist.xml`, newest day first
+pub fn parse_reference_rates(xml: &str) -> Result<Vec<ReferenceRates>> {
+    let doc =
+        roxmltree::Document::parse(xml).map_err(|e| Error::MalformedResponse(e.to_string()))?;
+    let mut days: Vec<ReferenceRates> = Vec::new();
+    for day in doc
+        .descendants()
+        .filter(|n| n.has_tag_name("Cube") && n.has_attribute("time"))
+    {
+        let mut rates: HashMap<String, Decimal> = HashMap::new();
+        rates.insert("EUR".to_string(), Decimal::ONE);
+        for cube in day.children().filter(|n| n.has_tag_name("Cube")) {
+            let (Some(code), Some(rate)) = (cube.attribute("currency"), cube.attribute("rate"))
+            else {
+                return Err(Error::MalformedResponse(format!(
+                    "Cube without currency or rate at {}",
+                    day.attribute("time").unwrap_or_default()
+                )));
+            };
+            let rate = Decimal::from_str(rate)
+                .ok()
+                .filter(is_valid_rate)
+                .ok_or_else(|| Error::MalformedResponse(format!("{} is not a valid rate", rate)))?;
+            rates.insert(code.to_uppercase(), rate);
+        }
+        days.push(ReferenceRates {
+            date: day.attribute("time").unwrap_or_default().to_string(),
+            rates,
+        });
+    }
+    days.sort_by(|a, b| b.date.cmp(&a.date));
+    Ok(days)
+}
+
+/// Unix time rates of `date` were published at
+pub fn publication_time(date: &str) -> Result<u64> {
+    let time = config::parse_date(date)
+        .ok_or_else(|| Error::MalformedResponse(format!("{} is not a valid date", date)))?;
+    Ok(time + PUBLICATION_TIME)
+}
+
+/// Unix time of next expected publication after rates of `date`, skipping weekends
+pub fn next_publication(date: &str) -> Result<u64> {
+    Ok(next_working_day(publication_time(date)?) + PUBLICATION_TIME)
+}
+
+pub struct EcbProvider {
+    endpoint: String,
+    history_endpoint: String,
+}
+
+impl EcbProvider {
+    pub fn new(endpoint: String, history_endpoint: String) -> Self {
+        EcbProvider {
+            endpoint,
+            history_endpoint,
+        }
+    }
+
+    fn fetch(endpoint: &str) -> Result<Vec<ReferenceRates>> {
+        let response = reqwest::blocking::get(endpoint)?;
+        if !response.status().is_success() {
+            return Err(Error::Api(response.status().to_string()));
+        }
+        parse_reference_rates(&response.text()?)
+    }
+
+    fn get_latest(&self) -> Result<ReferenceRates> {
+        Self::fetch(&self.endpoint)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::MalformedResponse("No reference rates found".to_string()))
+    }
+}
+
+/// Reference rates in effect on `date`, which are from last publication day not after it
+pub fn rates_on(days: Vec<ReferenceRates>, date: &str) -> Result<ReferenceRates> {
+    days.into_iter()
+        .find(|day| day.date.as_str() <= date)
+        .ok_or_else(|| Error::NoHistoricalRates(date.to_string()))
+}
+
+impl Provider for EcbProvider {
+    fn name(&self) -> &str {
+        NAME
+    }
+    fn requires_key(&self) -> bool {
+        false
+    }
+    fn get_currencies(&self) -> Result<Vec<[String; 2]>> {
+        let mut codes: Vec<[String; 2]> = self
+            .get_latest()?
+            .rates
+            .into_keys()
+            .map(|code| {
+                let name = find(&code).map(|c| c.name).unwrap_or_default().to_string();
+                [code, name]
+            })
+            .collect();
+        codes.sort();
+        Ok(codes)
+    }
+    fn get_rates(&self, code: &str) -> Result<Rates> {
+        let code = code.to_uppercase();
+        let latest = self.get_latest()?;
+        Ok(Rates {
+            time_last_update_unix: publication_time(&latest.date)?,
+            time_next_update_unix: next_publication(&latest.date)?,
+            conversion_rates: latest.rebase(&code)?,
+            effective_date: Some(latest.date),
+            table_no: None,
+            base_code: code,
+        })
+    }
+    fn get_historical_rates(&self, code: &str, date: &str) -> Result<Rates> {
+        let code = code.to_uppercase();
+        let time = config::parse_date(date).ok_or_else(|| Error::InvalidDate(date.to_string()))?;
+        let day = rates_on(Self::fetch(&self.history_endpoint)?, &config::format_date(time))?;
+        Ok(Rates {
+            time_last_update_unix: publication_time(&day.date)?,
+            time_next_update_unix: time,
+            conversion_rates: day.rebase(&code)?,
+            effective_date: Some(day.date),
+            table_no: None,
+            base_code: code,
+        })
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use super::{deserialize_rate, deserialize_rates, Provider, Rates};
+use crate::config;
+use crate::error::{Error, Result};
+
+pub const ID: &str = "exchangerate-api";
+pub const NAME: &str = "ExchangeRate-API";
+
+#[derive(Deserialize)]
+pub(super) struct CurrencyCodes {
+    pub supported_codes: Vec<[String; 2]>,
+}
+#[derive(Deserialize)]
+pub(super) struct ConversionRates {
+    pub base_code: String,
+    pub time_last_update_unix: u64,
+    pub time_next_update_unix: u64,
+    #[serde(deserialize_with = "deserialize_rates")]
+    pub conversion_rates: HashMap<String, Decimal>,
+}
+
+/// Response of `history` endpoint
+#[derive(Deserialize)]
+struct HistoricalRates {
+    base_code: String,
+    year: u32,
+    month: u32,
+    day: u32,
+    #[serde(deserialize_with = "deserialize_rates")]
+    conversion_rates: HashMap<String, Decimal>,
+}
+
+/// Response of `pair` endpoint, `conversion_result` is present only when amount is given
+#[derive(Deserialize)]
+pub struct PairConversion {
+    pub base_code: String,
+    pub target_code: String,
+    pub time_last_update_unix: u64,
+    pub time_next_update_unix: u64,
+    #[serde(deserialize_with = "deserialize_rate")]
+    pub conversion_rate: Decimal,
+    #[serde(default)]
+    pub conversion_result: Option<Decimal>,
+}
+
+/// Response of `quota` endpoint, cached with time it was checked at
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct Quota {
+    pub plan_quota: u64,
+    pub requests_remaining: u64,
+    pub refresh_day_of_month: u32,
+    /// Unix time quota was checked at, not part of the response
+    #[serde(default)]
+    pub time_checked_unix: u64,
+}
+
+impl Quota {
+    /// Whether less than [`config::QUOTA_LOW_PERCENT`] of monthly allowance is left
+    pub fn is_low(&self) -> bool {
+        self.requests_remaining * 100 < self.plan_quota * config::QUOTA_LOW_PERCENT
+    }
+}
+
+#[derive(Deserialize)]
+struct Err {
+    #[serde(rename = "error-type")]
+    error_type: String,
+}
+
+pub struct ExchangeRateApi {
+    endpoint: String,
+    api_key: String,
+}
+
+impl ExchangeRateApi {
+    pub fn new(endpoint: String, api_key: String) -> Self {
+        ExchangeRateApi { endpoint, api_key }
+    }
+
+    fn get(&self, path: &str, code: &str) -> Result<String> {
+        if self.api_key.is_empty() {
+            return Err(Error::InvalidKey);
+        }
+        let response =
+            reqwest::blocking::get(format!("{}{}{}", self.endpoint, self.api_key, path))?;
+        if !response.status().is_success() {
+            let err: Err = serde_json::from_str(&response.text()?)?;
+            return Err(match err.error_type.as_str() {
+                "invalid-key" | "inactive-account" => Error::InvalidKey,
+                "quota-reached" => Error::QuotaReached,
+                "unsupported-code" => Error::UnknownCode(code.to_string()),
+                "malformed-request" => Error::MalformedResponse(err.error_type),
+                _ => Error::Api(err.error_type),
+            });
+        }
+        Ok(response.text()?)
+    }
+}
+
+impl ExchangeRateApi {
+    /// Monthly allowance and requests remaining for API key, doesn't count towards quota
+    pub fn get_quota(&self) -> Result<Quota> {
+        let mut quota: Quota = serde_json::from_str(&self.get("/quota", "")?)?;
+        quota.time_checked_unix = config::get_current_time();
+        Ok(quota)
+    }
+    /// Converts `amount` on provider side with `pair` endpoint
+    pub fn convert(&self, code_from: &str, code_to: &str, amount: &str) -> Result<PairConversion> {
+        let path =
+            format!("/pair/{}/{}/{}", code_from.to_uppercase(), code_to.to_uppercase(), amount);
+        Ok(serde_json::from_str(&self.get(&path, code_from)?)?)
+    }
+}
+
+impl From<ConversionRates> for Rates {
+    fn from(response: ConversionRates) -> Self {
+        Rates {
+            base_code: response.base_code,
+            time_last_update_unix: response.time_last_update_unix,
+            time_next_update_unix: response.time_next_update_unix,
+            effective_date: None,
+            table_no: None,
+            conversion_rates: response.conversion_rates,
+        }
+    }
+}
+
+impl Provider for ExchangeRateApi {
+    fn name(&self) -> &str {
+        NAME
+    }
+    fn requires_key(&self) -> bool {
+        true
+    }
+    fn get_currencies(&self) -> Result<Vec<[String; 2]>> {
+        let codes: CurrencyCodes = serde_json::from_str(&self.get("/codes", "")?)?;
+        Ok(codes.supported_codes)
+    }
+    fn get_rates(&self, code: &str) -> Result<Rates> {
+        let code = code.to_uppercase();
+        let response: ConversionRates =
+            serde_json::from_str(&self.get(&format!("/latest/{}", code), &code)?)?;
+        Ok(response.into())
+    }
+    fn get_historical_rates(&self, code: &str, date: &str) -> Result<Rates> {
+        let code = code.to_uppercase();
+        let time = config::parse_date(date).ok_or_else(|| Error::InvalidDate(date.to_string()))?;
+        // Date in canonical `YYYY-MM-DD` form, as unpadded dates are accepted too
+        let path = format!("/history/{}/{}", code, config::format_date(time).replace('-', "/"));
+        let response: HistoricalRates = serde_json::from_str(&self.get(&path, &code)?)?;
+        Ok(Rates {
+            base_code: response.base_code,
+            time_last_update_unix: time,
+            time_next_update_unix: time,
+            effective_date: Some(format!(
+                "{:04}-{:02}-{:02}",
+                response.year, response.month, response.day
+            )),
+            table_no: None,
+            conversion_rates: response.conversion_rates,
+        })
+    }
+    fn get_pair(&self, code_from: &str, code_to: &str) -> Result<Option<Rates>> {
+        let path = format!("/pair/{}/{}", code_from.to_uppercase(), code_to.to_uppercase());
+        let response: PairConversion = serde_json::from_str(&self.get(&path, code_from)?)?;
+        Ok(Some(Rates {
+            base_code: response.base_code,
+            time_last_update_unix: response.time_last_update_unix,
+            time_next_update_unix: response.time_next_update_unix,
+            effective_date: None,
+            table_no: None,
+            conversion_rates: [(response.target_code, response.conversion_rate)].into(),
+        }))
+    }
+}
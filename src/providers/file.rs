@@ -0,0 +1,283 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use rust_decimal::Decimal;
+use rusty_money::iso::find;
+use serde::Deserialize;
+
+use super::{is_valid_rate, Provider, Rates};
+use crate::config;
+use crate::error::{Error, Result};
+
+pub const ID: &str = "file";
+pub const NAME: &str = "Rates file";
+
+/// Single rate read from file
+pub struct FileRate {
+    pub from: String,
+    pub to: String,
+    pub rate: Decimal,
+    /// Unix time rate was published at, 0 if unknown
+    pub timestamp: u64,
+}
+
+#[derive(Deserialize)]
+struct FileConversionRates {
+    base_code: String,
+    #[serde(default)]
+    time_last_update_unix: u64,
+    conversion_rates: HashMap<String, serde_json::Value>,
+}
+
+/// Three letter code in upper case
+pub(crate) fn parse_code(code: &str) -> Option<String> {
+    let code = code.trim();
+    if code.len() == 3 && code.chars().all(|c| c.is_ascii_alphabetic()) {
+        Some(code.to_uppercase())
+    } else {
+        None
+    }
+}
+
+/// Positive rate in decimal or exponent notation, normalized
+pub(crate) fn parse_rate(rate: &str) -> Option<Decimal> {
+    let rate = rate.trim();
+    Decimal::from_str(rate)
+        .or_else(|_| Decimal::from_scientific(rate))
+        .ok()
+        .filter(is_valid_rate)
+        .map(|rate| rate.normalize())
+}
+
+/// Unix time or date in `YYYY-MM-DD` format, empty for unknown
+fn parse_timestamp(timestamp: &str) -> Option<u64> {
+    let timestamp = timestamp.trim();
+    if timestamp.is_empty() {
+        return Some(0);
+    }
+    timestamp
+        .parse::<u64>()
+        .ok()
+        .or_else(|| config::parse_date(timestamp))
+}
+
+/// Parses CSV with `from,to,rate,timestamp` columns, header row is optional.
+/// Returns all invalid rows with their line numbers on error
+pub fn parse_csv(text: &str) -> std::result::Result<Vec<FileRate>, Vec<String>> {
+    let mut rates: Vec<FileRate> = Vec::new();
+    let mut errors: Vec<String> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line_no = i + 1;
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').collect();
+        if line_no == 1 && fields[0].trim().eq_ignore_ascii_case("from") {
+            continue;
+        }
+        if fields.len() < 3 || fields.len() > 4 {
+            errors.push(format!("line {}: expected from,to,rate,timestamp", line_no));
+            continue;
+        }
+        let from = parse_code(fields[0]);
+        let to = parse_code(fields[1]);
+        let rate = parse_rate(fields[2]);
+        let timestamp = parse_timestamp(fields.get(3).unwrap_or(&""));
+        match (from, to, rate, timestamp) {
+            (Some(from), Some(to), Some(rate), Some(timestamp)) => rates.push(FileRate {
+                from,
+                to,
+                rate,
+                timestamp,
+            }),
+            (None, ..) => {
+                errors.push(format!("line {}: invalid code {}", line_no, fields[0].trim()))
+            }
+            (_, None, ..) => {
+                errors.push(format!("line {}: invalid code {}", line_no, fields[1].trim()))
+            }
+            (_, _, None, _) => errors.push(format!(
+                "line {}: rate {} is not a positive number",
+                line_no,
+                fields[2].trim()
+            )),
+            (.., None) => errors.push(format!(
+                "line {}: invalid timestamp {}",
+                line_no,
+                fields[3].trim()
+            )),
+        }
+    }
+    if errors.is_empty() {
+        Ok(rates)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Parses JSON in ExchangeRate-API `latest` format, either single object or array of them
+pub fn parse_json(text: &str) -> std::result::Result<Vec<FileRate>, Vec<String>> {
+    let tables: Vec<FileConversionRates> = if text.trim_start().starts_with('[') {
+        serde_json::from_str(text)
+    } else {
+        serde_json::from_str(text).map(|table| vec![table])
+    }
+    .map_err(|e| vec![e.to_string()])?;
+
+    let mut rates: Vec<FileRate> = Vec::new();
+    let mut errors: Vec<String> = Vec::new();
+    for table in tables {
+        let Some(from) = parse_code(&table.base_code) else {
+            errors.push(format!("invalid base_code {}", table.base_code));
+            continue;
+        };
+        for (to, rate) in table.conversion_rates {
+            let text_rate = match &rate {
+                serde_json::Value::String(rate) => rate.clone(),
+                rate => rate.to_string(),
+            };
+            match (parse_code(&to), parse_rate(&text_rate)) {
+                (Some(to), Some(rate)) => rates.push(FileRate {
+                    from: from.clone(),
+                    to,
+                    rate,
+                    timestamp: table.time_last_update_unix,
+                }),
+                (None, _) => errors.push(format!("{}: invalid code {}", from, to)),
+                (_, None) => errors.push(format!(
+                    "{} to {}: rate {} is not a positive number",
+                    from, to, text_rate
+                )),
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(rates)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Provider reading rates from local CSV or JSON file, JSON is recognized by `.json` extension
+pub struct FileProvider {
+    path: PathBuf,
+}
+
+impl FileProvider {
+    pub fn new(path: PathBuf) -> Self {
+        FileProvider { path }
+    }
+
+    pub fn read(&self) -> Result<Vec<FileRate>> {
+        let to_error = |errors: Vec<String>| Error::RatesFile {
+            path: self.path.display().to_string(),
+            errors,
+        };
+        let text =
+            std::fs::read_to_string(&self.path).map_err(|e| to_error(vec![e.to_string()]))?;
+        if is_json(&self.path) {
+            parse_json(&text).map_err(to_error)
+        } else {
+            parse_csv(&text).map_err(to_error)
+        }
+    }
+}
+
+/// Whether file at `path` is JSON, recognized by `.json` extension
+pub(crate) fn is_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+}
+
+impl Provider for FileProvider {
+    fn name(&self) -> &str {
+        NAME
+    }
+    fn requires_key(&self) -> bool {
+        false
+    }
+    fn get_currencies(&self) -> Result<Vec<[String; 2]>> {
+        let mut codes: Vec<String> = self
+            .read()?
+            .into_iter()
+            .flat_map(|rate| [rate.from, rate.to])
+            .collect();
+        codes.sort();
+        codes.dedup();
+        Ok(codes
+            .into_iter()
+            .map(|code| {
+                let name = find(&code).map(|c| c.name).unwrap_or_default().to_string();
+                [code, name]
+            })
+            .collect())
+    }
+    fn get_rates(&self, code: &str) -> Result<Rates> {
+        self.get_rates_until(code, u64::MAX)
+    }
+    fn get_historical_rates(&self, code: &str, date: &str) -> Result<Rates> {
+        let time = config::parse_date(date).ok_or_else(|| Error::InvalidDate(date.to_string()))?;
+        // Rates without timestamp are not used for history
+        self.get_rates_until(code, time + 86400 - 1)
+    }
+}
+
+impl FileProvider {
+    /// Uses rates with `code` as base, or inverted rates with `code` as target if there is
+    /// no direct one. Newest rate not after `until` wins when pair is in file more than once
+    fn get_rates_until(&self, code: &str, until: u64) -> Result<Rates> {
+        let code = code.to_uppercase();
+        let history = until != u64::MAX;
+        let mut direct: HashMap<String, (u64, Decimal)> = HashMap::new();
+        let mut inverse: HashMap<String, (u64, Decimal)> = HashMap::new();
+        let mut known = false;
+        for rate in self.read()? {
+            known = known || rate.from == code || rate.to == code;
+            if rate.timestamp > until || (history && rate.timestamp == 0) {
+                continue;
+            }
+            let (rates, code_to, value) = if rate.from == code {
+                (&mut direct, rate.to, rate.rate)
+            } else if rate.to == code {
+                let value = (Decimal::ONE / rate.rate).round_sf(8).unwrap_or_default();
+                (&mut inverse, rate.from, value.normalize())
+            } else {
+                continue;
+            };
+            match rates.get(&code_to) {
+                Some((time, _)) if *time > rate.timestamp => {}
+                _ => {
+                    rates.insert(code_to, (rate.timestamp, value));
+                }
+            }
+        }
+        if direct.is_empty() && inverse.is_empty() {
+            return Err(if known {
+                Error::NoHistoricalRates(config::format_date(until))
+            } else {
+                Error::UnknownCode(code)
+            });
+        }
+        for (code_to, rate) in inverse {
+            direct.entry(code_to).or_insert(rate);
+        }
+        direct.insert(code.clone(), (0, Decimal::ONE));
+        let last_update = direct.values().map(|(time, _)| *time).max().unwrap_or(0);
+
+        Ok(Rates {
+            time_last_update_unix: last_update,
+            // File is cheap to read again, so rates are never considered fresh
+            time_next_update_unix: config::get_current_time(),
+            effective_date: (last_update > 0).then(|| config::format_date(last_update)),
+            table_no: None,
+            conversion_rates: direct
+                .into_iter()
+                .map(|(code_to, (_, rate))| (code_to, rate))
+                .collect(),
+            base_code: code,
+        })
+    }
+}
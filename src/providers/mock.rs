@@ -0,0 +1,76 @@
+use std::cell::Cell;
+
+use super::exchangerate_api::{ConversionRates, CurrencyCodes};
+use super::{Provider, Rates};
+use crate::config;
+use crate::error::{Error, Result};
+
+pub const ID: &str = "mock";
+pub const NAME: &str = "Mock data";
+
+thread_local! {
+    static FETCHES: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Rates served by mock provider on current thread, so tests can count fetches
+pub fn fetch_count() -> usize {
+    FETCHES.with(Cell::get)
+}
+
+/// Offline provider serving bundled `mock_data` in ExchangeRate-API format
+pub struct MockProvider;
+
+impl Provider for MockProvider {
+    fn name(&self) -> &str {
+        NAME
+    }
+    fn requires_key(&self) -> bool {
+        false
+    }
+    fn get_currencies(&self) -> Result<Vec<[String; 2]>> {
+        let codes: CurrencyCodes = serde_json::from_str(include_str!(concat!(
+            "..",
+            crate::main_separator!(),
+            "mock_data",
+            crate::main_separator!(),
+            "codes.json"
+        )))?;
+        Ok(codes.supported_codes)
+    }
+    fn get_rates(&self, code: &str) -> Result<Rates> {
+        FETCHES.with(|fetches| fetches.set(fetches.get() + 1));
+        let response: ConversionRates = match code.to_uppercase().as_str() {
+            "PLN" => serde_json::from_str(include_str!(concat!(
+                "..",
+                crate::main_separator!(),
+                "mock_data",
+                crate::main_separator!(),
+                "PLN.json"
+            )))?,
+            "EUR" => serde_json::from_str(include_str!(concat!(
+                "..",
+                crate::main_separator!(),
+                "mock_data",
+                crate::main_separator!(),
+                "EUR.json"
+            )))?,
+            _ => return Err(Error::UnknownCode(code.to_string())),
+        };
+        Ok(response.into())
+    }
+    fn get_historical_rates(&self, code: &str, date: &str) -> Result<Rates> {
+        config::parse_date(date).ok_or_else(|| Error::InvalidDate(date.to_string()))?;
+        let mut rates = self.get_rates(code)?;
+        rates.effective_date = Some(date.to_string());
+        Ok(rates)
+    }
+    fn get_pair(&self, code_from: &str, code_to: &str) -> Result<Option<Rates>> {
+        let mut rates = self.get_rates(code_from)?;
+        let rate = rates
+            .conversion_rates
+            .remove(&code_to.to_uppercase())
+            .ok_or_else(|| Error::UnknownCode(code_to.to_string()))?;
+        rates.conversion_rates = [(code_to.to_uppercase(), rate)].into();
+        Ok(Some(rates))
+    }
+}
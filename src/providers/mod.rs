@@ -0,0 +1,233 @@
+use std::{collections::HashMap, env::var_os, path::PathBuf};
+
+use rust_decimal::Decimal;
+use serde::{de::Error as _, Deserialize, Deserializer};
+
+use crate::error::{Error, Result};
+use crate::cache::{self, Cache};
+use crate::config;
+
+pub mod ecb;
+pub mod exchangerate_api;
+pub mod file;
+pub mod mock;
+pub mod nbp;
+
+pub use ecb::EcbProvider;
+pub use exchangerate_api::ExchangeRateApi;
+pub use file::FileProvider;
+pub use mock::MockProvider;
+pub use nbp::NbpProvider;
+
+pub struct Rates {
+    pub base_code: String,
+    /// Unix time rates were published at by provider, 0 if unknown
+    pub time_last_update_unix: u64,
+    pub time_next_update_unix: u64,
+    /// Date rates are effective for in `YYYY-MM-DD` format, if provider publishes it
+    pub effective_date: Option<String>,
+    /// Number of published rate table, if provider publishes it
+    pub table_no: Option<String>,
+    /// Validated rates, see [`is_valid_rate`]
+    pub conversion_rates: HashMap<String, Decimal>,
+}
+
+/// Source of currency codes and exchange rates
+pub trait Provider {
+    /// Human readable name of the provider
+    fn name(&self) -> &str;
+    /// Whether provider needs API key set up with `--set-api-key`
+    fn requires_key(&self) -> bool;
+    /// Supported currencies as `[code, name]` pairs
+    fn get_currencies(&self) -> Result<Vec<[String; 2]>>;
+    /// Rates for all currencies with `code` as base
+    fn get_rates(&self, code: &str) -> Result<Rates>;
+    /// Rates for all currencies with `code` as base, as they were on `date` in `YYYY-MM-DD` format
+    fn get_historical_rates(&self, _code: &str, date: &str) -> Result<Rates> {
+        Err(Error::NoHistoricalRates(date.to_string()))
+    }
+    /// Rate of single pair, `None` if provider can only fetch whole rate tables
+    fn get_pair(&self, _code_from: &str, _code_to: &str) -> Result<Option<Rates>> {
+        Ok(None)
+    }
+}
+
+pub const PROVIDER_NAMES: [&str; 7] = [
+    exchangerate_api::ID,
+    ecb::ID,
+    nbp::ID_TABLE_A,
+    nbp::ID_TABLE_B,
+    nbp::ID_TABLE_C,
+    file::ID,
+    mock::ID,
+];
+
+pub fn get_provider_by_name(name: &str) -> Result<Box<dyn Provider>> {
+    provider_by_name(name, cache::get_api_key, || {
+        cache::get_config(config::RATES_FILE_CONFIG_NAME)
+    })
+}
+
+/// Same as [`get_provider_by_name`], reading API key and rates file from already open `cache`
+pub fn get_provider_with(cache: &Cache, name: &str) -> Result<Box<dyn Provider>> {
+    provider_by_name(name, || cache.get_api_key(), || {
+        cache.get_config(config::RATES_FILE_CONFIG_NAME)
+    })
+}
+
+fn provider_by_name(
+    name: &str,
+    get_api_key: impl FnOnce() -> rusqlite::Result<String>,
+    get_rates_file_config: impl FnOnce() -> rusqlite::Result<Option<String>>,
+) -> Result<Box<dyn Provider>> {
+    match name {
+        exchangerate_api::ID => {
+            Ok(Box::new(ExchangeRateApi::new(config::get_endpoint(), get_api_key()?)))
+        }
+        ecb::ID => Ok(Box::new(EcbProvider::new(
+            config::ECB_DAILY_ENDPOINT.to_string(),
+            config::ECB_HIST_ENDPOINT.to_string(),
+        ))),
+        nbp::ID_TABLE_A => Ok(Box::new(NbpProvider::new(config::NBP_ENDPOINT.to_string(), "A"))),
+        nbp::ID_TABLE_B => Ok(Box::new(NbpProvider::new(config::NBP_ENDPOINT.to_string(), "B"))),
+        nbp::ID_TABLE_C => Ok(Box::new(NbpProvider::new(config::NBP_ENDPOINT.to_string(), "C"))),
+        file::ID => Ok(Box::new(FileProvider::new(rates_file(get_rates_file_config)?))),
+        mock::ID => Ok(Box::new(MockProvider)),
+        _ => Err(Error::UnknownProvider(name.to_string())),
+    }
+}
+
+/// Names of providers in priority order from `CURRENCY_PROVIDER` env variable, or set with
+/// `--set-provider`
+pub fn get_provider_names() -> Result<Vec<String>> {
+    provider_names(None, || cache::get_config(config::PROVIDER_CONFIG_NAME))
+}
+
+/// Same as [`get_provider_names`], reading configuration from already open `cache` and
+/// preferring providers overridden for it
+pub fn get_provider_names_with(cache: &Cache) -> Result<Vec<String>> {
+    let overridden = cache.overrides().providers.as_ref().map(|names| names.join(","));
+    provider_names(overridden, || cache.get_config(config::PROVIDER_CONFIG_NAME))
+}
+
+fn provider_names(
+    overridden: Option<String>,
+    get_config: impl FnOnce() -> rusqlite::Result<Option<String>>,
+) -> Result<Vec<String>> {
+    let names = match overridden {
+        Some(names) => names,
+        None => match var_os(config::PROVIDER_ENV_NAME) {
+            Some(val) => val.to_string_lossy().to_string(),
+            None => get_config()?.unwrap_or(exchangerate_api::ID.to_string()),
+        },
+    };
+    let names: Vec<String> = names
+        .split(',')
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect();
+    if let Some(name) = names.iter().find(|name| !PROVIDER_NAMES.contains(&name.as_str())) {
+        return Err(Error::UnknownProvider(name.clone()));
+    }
+    if names.is_empty() {
+        return Err(Error::UnknownProvider(String::new()));
+    }
+    Ok(names)
+}
+
+/// Human readable name of provider with ID `name`, without setting provider up. Falls back to
+/// `name` for unknown provider
+pub fn get_display_name(name: &str) -> String {
+    match name {
+        exchangerate_api::ID => exchangerate_api::NAME,
+        ecb::ID => ecb::NAME,
+        nbp::ID_TABLE_A => nbp::NAME_TABLE_A,
+        nbp::ID_TABLE_B => nbp::NAME_TABLE_B,
+        nbp::ID_TABLE_C => nbp::NAME_TABLE_C,
+        file::ID => file::NAME,
+        mock::ID => mock::NAME,
+        name => name,
+    }
+    .to_string()
+}
+
+/// Path of rates file from `CURRENCY_RATES_FILE` env variable, or set with `--set-rates-file`
+pub fn get_rates_file() -> Result<PathBuf> {
+    rates_file(|| cache::get_config(config::RATES_FILE_CONFIG_NAME))
+}
+
+fn rates_file(
+    get_config: impl FnOnce() -> rusqlite::Result<Option<String>>,
+) -> Result<PathBuf> {
+    if let Some(val) = var_os(config::RATES_FILE_ENV_NAME) {
+        return Ok(PathBuf::from(val));
+    }
+    get_config()?
+        .map(PathBuf::from)
+        .ok_or_else(|| Error::RatesFile {
+            path: String::new(),
+            errors: vec!["Rates file is not set up, use --set-rates-file".to_string()],
+        })
+}
+
+/// Whether every provider in chain needs API key
+pub fn requires_key() -> Result<bool> {
+    chain_requires_key(&get_provider_names()?, get_provider_by_name)
+}
+
+/// Same as [`requires_key`] for provider chain of `cache`
+pub fn requires_key_with(cache: &Cache) -> Result<bool> {
+    chain_requires_key(&get_provider_names_with(cache)?, |name| get_provider_with(cache, name))
+}
+
+fn chain_requires_key(
+    names: &[String],
+    get_provider: impl Fn(&str) -> Result<Box<dyn Provider>>,
+) -> Result<bool> {
+    for name in names {
+        if !get_provider(name)?.requires_key() {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Rates are divided by and converted with, so they have to be positive
+pub fn is_valid_rate(rate: &Decimal) -> bool {
+    rate.is_sign_positive() && !rate.is_zero()
+}
+
+/// Deserializes exact decimal rate, rejecting invalid ones
+fn deserialize_rate<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Decimal, D::Error> {
+    let rate = <Decimal as Deserialize>::deserialize(deserializer)?;
+    if !is_valid_rate(&rate) {
+        return Err(D::Error::custom(format!("rate {} is not a positive number", rate)));
+    }
+    Ok(rate)
+}
+
+/// Deserializes map of exact decimal rates, rejecting whole map if any rate is invalid
+fn deserialize_rates<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<HashMap<String, Decimal>, D::Error> {
+    let rates = HashMap::<String, Decimal>::deserialize(deserializer)?;
+    if let Some((code, rate)) = rates.iter().find(|(_, rate)| !is_valid_rate(rate)) {
+        return Err(D::Error::custom(format!(
+            "rate {} of {} is not a positive number",
+            rate, code
+        )));
+    }
+    Ok(rates)
+}
+
+/// Midnight of next working day after `time`, skipping weekends
+fn next_working_day(time: u64) -> u64 {
+    let mut days = time / 86400 + 1;
+    // 1970-01-01 was Thursday, so 2 is Saturday and 3 is Sunday
+    while days % 7 == 2 || days % 7 == 3 {
+        days += 1;
+    }
+    days * 86400
+}
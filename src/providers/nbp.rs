@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use super::{is_valid_rate, next_working_day, Provider, Rates};
+use crate::config;
+use crate::error::{Error, Result};
+
+pub const ID_TABLE_A: &str = "nbp-a";
+pub const ID_TABLE_B: &str = "nbp-b";
+pub const ID_TABLE_C: &str = "nbp-c";
+pub const NAME_TABLE_A: &str = "NBP table A";
+pub const NAME_TABLE_B: &str = "NBP table B";
+pub const NAME_TABLE_C: &str = "NBP table C";
+
+/// Table A and C are published around noon on working days
+const PUBLICATION_TIME: u64 = 11 * 3600;
+/// How far before requested date historical tables are looked up
+const HISTORY_WINDOW: u64 = 14 * 86400;
+
+/// Rate of JSON API, which quotes every currency per unit, unlike XML tables quoting some per 100
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NbpRate {
+    pub currency: String,
+    pub code: String,
+    pub mid: Option<Decimal>,
+    pub bid: Option<Decimal>,
+    pub ask: Option<Decimal>,
+}
+
+impl NbpRate {
+    /// PLN received for one unit of currency
+    fn sell(&self) -> Option<Decimal> {
+        self.bid.or(self.mid)
+    }
+    /// PLN paid for one unit of currency
+    fn buy(&self) -> Option<Decimal> {
+        self.ask.or(self.mid)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NbpTable {
+    pub table: String,
+    pub no: String,
+    pub effective_date: String,
+    pub rates: Vec<NbpRate>,
+}
+
+impl NbpTable {
+    /// Rates with `code` as base. Table C uses bid when selling `code` and ask when buying
+    /// target currency, tables A and B use mid rate for both
+    pub fn rebase(&self, code: &str) -> Result<HashMap<String, Decimal>> {
+        let sell = if code == "PLN" {
+            Decimal::ONE
+        } else {
+            self.rates
+                .iter()
+                .find(|rate| rate.code == code)
+                .and_then(NbpRate::sell)
+                .ok_or_else(|| Error::UnknownCode(code.to_string()))?
+        };
+        let mut rates: HashMap<String, Decimal> = HashMap::new();
+        rates.insert("PLN".to_string(), sell.round_sf(8).unwrap_or_default().normalize());
+        for rate in &self.rates {
+            let Some(buy) = rate.buy().filter(|buy| !buy.is_zero()) else {
+                return Err(Error::MalformedResponse(format!("No rate for {}", rate.code)));
+            };
+            let cross = (sell / buy).round_sf(8).unwrap_or_default().normalize();
+            rates.insert(rate.code.clone(), cross);
+        }
+        rates.insert(code.to_string(), Decimal::ONE);
+        Ok(rates)
+    }
+
+    /// Unix time table was published at
+    pub fn publication_time(&self) -> Result<u64> {
+        let time = config::parse_date(&self.effective_date).ok_or_else(|| {
+            Error::MalformedResponse(format!("{} is not a valid date", self.effective_date))
+        })?;
+        Ok(time + PUBLICATION_TIME)
+    }
+
+    /// Unix time when next table is expected, table B is published on Wednesdays
+    pub fn next_publication(&self) -> Result<u64> {
+        let time = self.publication_time()? - PUBLICATION_TIME;
+        if self.table == "B" {
+            // 1970-01-01 was Thursday, Wednesdays are 6 days later
+            let days = time / 86400;
+            return Ok((days + 7 - (days + 1) % 7) * 86400 + PUBLICATION_TIME);
+        }
+        Ok(next_working_day(time) + PUBLICATION_TIME)
+    }
+}
+
+pub fn parse_tables(json: &str) -> Result<Vec<NbpTable>> {
+    let tables: Vec<NbpTable> = serde_json::from_str(json)?;
+    for rate in tables.iter().flat_map(|table| &table.rates) {
+        let values = [rate.mid, rate.bid, rate.ask];
+        if !values.iter().flatten().all(is_valid_rate) {
+            return Err(Error::MalformedResponse(format!("Invalid rate of {}", rate.code)));
+        }
+    }
+    Ok(tables)
+}
+
+pub struct NbpProvider {
+    endpoint: String,
+    table: &'static str,
+}
+
+impl NbpProvider {
+    /// Provider for NBP table `A`, `B` or `C`
+    pub fn new(endpoint: String, table: &'static str) -> Self {
+        NbpProvider { endpoint, table }
+    }
+
+    fn get_tables(&self, path: &str) -> Result<Vec<NbpTable>> {
+        let url = format!("{}{}/{}?format=json", self.endpoint, self.table, path);
+        let response = reqwest::blocking::get(url)?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(Vec::new());
+        }
+        if !response.status().is_success() {
+            return Err(Error::Api(response.status().to_string()));
+        }
+        parse_tables(&response.text()?)
+    }
+
+    fn get_table(&self) -> Result<NbpTable> {
+        self.get_tables("")?
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::MalformedResponse("No NBP table found".to_string()))
+    }
+}
+
+/// Table in effect on `date`, which is last one published not after it
+pub fn table_on(tables: Vec<NbpTable>, date: &str) -> Result<NbpTable> {
+    tables
+        .into_iter()
+        .filter(|table| table.effective_date.as_str() <= date)
+        .max_by(|a, b| a.effective_date.cmp(&b.effective_date))
+        .ok_or_else(|| Error::NoHistoricalRates(date.to_string()))
+}
+
+impl Provider for NbpProvider {
+    fn name(&self) -> &str {
+        match self.table {
+            "A" => NAME_TABLE_A,
+            "B" => NAME_TABLE_B,
+            _ => NAME_TABLE_C,
+        }
+    }
+    fn requires_key(&self) -> bool {
+        false
+    }
+    fn get_currencies(&self) -> Result<Vec<[String; 2]>> {
+        let mut codes: Vec<[String; 2]> = self
+            .get_table()?
+            .rates
+            .into_iter()
+            .map(|rate| [rate.code, rate.currency])
+            .collect();
+        codes.push(["PLN".to_string(), "złoty polski".to_string()]);
+        codes.sort();
+        Ok(codes)
+    }
+    fn get_rates(&self, code: &str) -> Result<Rates> {
+        let code = code.to_uppercase();
+        let table = self.get_table()?;
+        Ok(Rates {
+            time_last_update_unix: table.publication_time()?,
+            time_next_update_unix: table.next_publication()?,
+            conversion_rates: table.rebase(&code)?,
+            effective_date: Some(table.effective_date),
+            table_no: Some(table.no),
+            base_code: code,
+        })
+    }
+    fn get_historical_rates(&self, code: &str, date: &str) -> Result<Rates> {
+        let code = code.to_uppercase();
+        let time = config::parse_date(date).ok_or_else(|| Error::InvalidDate(date.to_string()))?;
+        // Table B is weekly and holidays can be long, so look two weeks back
+        let start = config::format_date(time.saturating_sub(HISTORY_WINDOW));
+        let date = config::format_date(time);
+        let table = table_on(self.get_tables(&format!("{}/{}/", start, date))?, &date)?;
+        Ok(Rates {
+            time_last_update_unix: table.publication_time()?,
+            time_next_update_unix: time,
+            conversion_rates: table.rebase(&code)?,
+            effective_date: Some(table.effective_date),
+            table_no: Some(table.no),
+            base_code: code,
+        })
+    }
+}
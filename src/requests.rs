@@ -1,140 +1,134 @@
-use std::collections::HashMap;
+use std::env::var_os;
 
-use crate::cache::{self, get_api_key};
-use crate::config::get_endpoint;
-use serde::Deserialize;
-#[derive(PartialEq)]
-pub enum Status {
-    OK,
-    INVALID,
-    LIMIT,
-    ERROR,
-}
+use crate::error::{Error, Result};
+use crate::providers::exchangerate_api::{self, Quota};
+use crate::providers::{get_provider_by_name, get_provider_names, ExchangeRateApi};
+use crate::cache::{self, Cache};
+use crate::config;
 
-#[derive(Deserialize)]
-struct CurrencyCodes {
-    supported_codes: Vec<[String; 2]>,
+#[derive(PartialEq, Debug)]
+pub enum FetchStrategy {
+    /// Fetch only requested pair, when provider supports it
+    Pair,
+    /// Fetch all rates of base currency
+    Table,
 }
-#[derive(Deserialize)]
-struct ConversionRates {
-    base_code: String,
-    time_next_update_unix: u64,
 
-    conversion_rates: HashMap<String, serde_json::Value>,
+pub const FETCH_STRATEGY_NAMES: [&str; 2] = ["pair", "table"];
+
+/// Strategy from `CURRENCY_FETCH_STRATEGY` env variable or set with `--set-fetch-strategy`
+pub fn get_fetch_strategy(cache: &Cache) -> Result<FetchStrategy> {
+    let strategy = match var_os(config::FETCH_STRATEGY_ENV_NAME) {
+        Some(val) => Some(val.to_string_lossy().to_string()),
+        None => cache.get_config(config::FETCH_STRATEGY_CONFIG_NAME)?,
+    };
+    Ok(match strategy.as_deref() {
+        Some("pair") => FetchStrategy::Pair,
+        _ => FetchStrategy::Table,
+    })
 }
 
-#[derive(Deserialize)]
-struct Err {
-    #[serde(rename = "error-type")]
-    error_type: String,
+/// Fetches ExchangeRate-API quota and caches it
+pub fn get_quota() -> Result<Quota> {
+    let quota = ExchangeRateApi::new(config::get_endpoint(), cache::get_api_key()?).get_quota()?;
+    cache::set_config(config::QUOTA_CONFIG_NAME, &serde_json::to_string(&quota)?)?;
+    Ok(quota)
 }
-#[cfg(test)]
-mod test {
-    pub fn mock_get_rates(code: &String) -> Result<super::Status, reqwest::Error> {
-        let response: super::ConversionRates = match code.as_str() {
-            "PLN" => serde_json::from_str(include_str!(concat!(
-                ".",
-                crate::main_separator!(),
-                "mock_data",
-                crate::main_separator!(),
-                "PLN.json"
-            )))
-            .expect("Error when deserializng"),
-            "EUR" => serde_json::from_str(include_str!(concat!(
-                ".",
-                crate::main_separator!(),
-                "mock_data",
-                crate::main_separator!(),
-                "EUR.json"
-            )))
-            .expect("Error when deserializng"),
-            _ => {
-                panic!("Unknown code")
-            }
-        };
-        crate::cache::add_rates(
-            response.time_next_update_unix,
-            &response.base_code,
-            &response.conversion_rates,
-        )
-        .expect("Error while caching response");
-        Ok(super::Status::OK)
+/// Quota cached by last [`get_quota`], with requests made since then subtracted
+pub fn get_cached_quota() -> Result<Option<Quota>> {
+    match cache::get_config(config::QUOTA_CONFIG_NAME)? {
+        Some(quota) => Ok(Some(serde_json::from_str(&quota)?)),
+        None => Ok(None),
     }
-    pub fn mock_get_currencies() -> Result<super::Status, reqwest::Error> {
-        let codes: super::CurrencyCodes = serde_json::from_str(include_str!(concat!(
-            ".",
-            crate::main_separator!(),
-            "mock_data",
-            crate::main_separator!(),
-            "codes.json"
-        )))
-        .expect("Error when deserializng");
-        for code in codes.supported_codes {
-            crate::cache::add_code(code).expect("Error when adding code to cache");
-        }
-        return Ok(super::Status::OK);
+}
+/// Keeps cached quota in line with requests made, without asking the API
+fn update_cached_quota(name: &str, result: &Result<impl Sized>) -> Result<()> {
+    if name != exchangerate_api::ID {
+        return Ok(());
     }
+    let Some(mut quota) = get_cached_quota()? else {
+        return Ok(());
+    };
+    quota.requests_remaining = match result {
+        Ok(_) => quota.requests_remaining.saturating_sub(1),
+        Err(Error::QuotaReached) => 0,
+        Err(_) => return Ok(()),
+    };
+    cache::set_config(config::QUOTA_CONFIG_NAME, &serde_json::to_string(&quota)?)?;
+    Ok(())
 }
 
-pub fn get_rates(code: &String) -> Result<Status, reqwest::Error> {
-    if cfg!(test) {
-        #[cfg(test)]
-        return test::mock_get_rates(code);
-    }
-    let response = reqwest::blocking::get(format!(
-        "{}{}{}{}",
-        get_endpoint(),
-        get_api_key().expect("Error when getting api key from cache"),
-        "/latest/",
-        code.to_uppercase()
-    ))?;
-    if response.status().is_success() {
-        let response: ConversionRates =
-            serde_json::from_str(&response.text()?).expect("Error when deserializng");
-        cache::add_rates(
-            response.time_next_update_unix,
-            &response.base_code,
-            &response.conversion_rates,
-        )
-        .expect("Error while caching response");
-        return Ok(Status::OK);
-    } else {
-        let err: Err = serde_json::from_str(&response.text()?).expect("Error when deserializng");
-        if err.error_type == "invalid-key" {
-            return Ok(Status::INVALID);
-        } else if err.error_type == "quota-reached" {
-            return Ok(Status::LIMIT);
+/// Runs `f` on providers in priority order until one succeeds
+fn with_fallback<T>(names: &[String], f: impl Fn(&str) -> Result<T>) -> Result<T> {
+    let mut errors: Vec<(String, Error)> = Vec::new();
+    for name in names {
+        let result = f(name);
+        update_cached_quota(name, &result)?;
+        match result {
+            Ok(value) => return Ok(value),
+            Err(e) => errors.push((name.clone(), e)),
         }
     }
+    if errors.is_empty() {
+        return Err(Error::UnknownProvider(String::new()));
+    }
+    if errors.len() == 1 {
+        return Err(errors.remove(0).1);
+    }
+    Err(Error::AllProvidersFailed(errors))
+}
 
-    Ok(Status::ERROR)
+/// Fetches rates with `code` as base and caches them, returns name of provider that answered
+pub fn get_rates(code: &str) -> Result<String> {
+    get_rates_from(&get_provider_names()?, code)
 }
-pub fn get_currencies() -> Result<Status, reqwest::Error> {
-    if cfg!(test) {
-        #[cfg(test)]
-        return test::mock_get_currencies();
-    }
-    let response = reqwest::blocking::get(format!(
-        "{}{}{}",
-        get_endpoint(),
-        get_api_key().expect("Error when getting api key from cache"),
-        "/codes"
-    ))?;
-    if response.status().is_success() {
-        let codes: CurrencyCodes =
-            serde_json::from_str(&response.text()?).expect("Error when deserializng");
-        for code in codes.supported_codes {
-            cache::add_code(code).expect("Error when adding code to cache");
-        }
-        return Ok(Status::OK);
-    } else {
-        let err: Err = serde_json::from_str(&response.text()?).expect("Error when deserializng");
-        if err.error_type == "invalid-key" {
-            return Ok(Status::INVALID);
-        } else if err.error_type == "quota-reached" {
-            return Ok(Status::LIMIT);
+/// Same as [`get_rates`], but with provider chain `names` instead of configured one
+pub fn get_rates_from(names: &[String], code: &str) -> Result<String> {
+    with_fallback(names, |name| {
+        let rates = get_provider_by_name(name)?.get_rates(code)?;
+        cache::add_rates(name, &rates)?;
+        Ok(name.to_string())
+    })
+}
+/// Fetches rates with `code` as base as they were on `date` and caches them under that date,
+/// returns name of provider that answered
+pub fn get_historical_rates(code: &str, date: &str) -> Result<String> {
+    get_historical_rates_from(&get_provider_names()?, code, date)
+}
+/// Same as [`get_historical_rates`], but with provider chain `names` instead of configured one
+pub fn get_historical_rates_from(names: &[String], code: &str, date: &str) -> Result<String> {
+    with_fallback(names, |name| {
+        let rates = get_provider_by_name(name)?.get_historical_rates(code, date)?;
+        cache::add_historical_rates(name, date, &rates)?;
+        Ok(name.to_string())
+    })
+}
+/// Fetches and caches single pair, falling back to whole rate table for providers without
+/// pair support. Returns name of provider that answered
+pub fn get_pair(code_from: &str, code_to: &str) -> Result<String> {
+    get_pair_from(&get_provider_names()?, code_from, code_to)
+}
+/// Same as [`get_pair`], but with provider chain `names` instead of configured one
+pub fn get_pair_from(names: &[String], code_from: &str, code_to: &str) -> Result<String> {
+    with_fallback(names, |name| {
+        let provider = get_provider_by_name(name)?;
+        match provider.get_pair(code_from, code_to)? {
+            Some(rates) => cache::add_pair_rates(name, &rates)?,
+            None => cache::add_rates(name, &provider.get_rates(code_from)?)?,
         }
+        Ok(name.to_string())
+    })
+}
+pub fn get_currencies() -> Result<()> {
+    get_chain_currencies(&get_provider_names()?)
+}
+/// Same as [`get_currencies`], but with provider chain `names` instead of configured one
+pub fn get_chain_currencies(names: &[String]) -> Result<()> {
+    with_fallback(names, get_currencies_from)
+}
+pub fn get_currencies_from(name: &str) -> Result<()> {
+    for code in get_provider_by_name(name)?.get_currencies()? {
+        cache::add_code(code)?;
     }
-
-    Ok(Status::ERROR)
+    Ok(())
 }
@@ -0,0 +1,187 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::cache::{self, CurrencyRow, RateRow};
+use crate::error::{Error, Result};
+use crate::providers::file::{is_json, parse_code, parse_rate};
+
+/// Cached currencies and rates, without configuration and API key
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Snapshot {
+    pub currencies: Vec<CurrencyRow>,
+    pub exchange_rates: Vec<RateRow>,
+}
+
+fn to_error(path: &Path, errors: Vec<String>) -> Error {
+    Error::Snapshot {
+        path: path.display().to_string(),
+        errors,
+    }
+}
+
+/// Writes cached currencies and rates to `path`, as JSON for `.json` extension and CSV otherwise
+pub fn export(path: &Path) -> Result<Snapshot> {
+    let snapshot = Snapshot {
+        currencies: cache::list_currency_rows()?,
+        exchange_rates: cache::list_rate_rows()?,
+    };
+    let text = if is_json(path) {
+        serde_json::to_string_pretty(&snapshot)?
+    } else {
+        to_csv(&snapshot).map_err(|e| to_error(path, vec![e]))?
+    };
+    std::fs::write(path, text).map_err(|e| to_error(path, vec![e.to_string()]))?;
+    Ok(snapshot)
+}
+
+/// Merges snapshot from `path` into cache, keeping newer of cached and imported rates.
+/// Returns number of currencies and rates added or replaced
+pub fn import(path: &Path) -> Result<(usize, usize)> {
+    let text = std::fs::read_to_string(path).map_err(|e| to_error(path, vec![e.to_string()]))?;
+    let snapshot = if is_json(path) {
+        parse_json(&text)
+    } else {
+        parse_csv(&text)
+    }
+    .map_err(|errors| to_error(path, errors))?;
+    Ok(cache::merge_rows(&snapshot.currencies, &snapshot.exchange_rates)?)
+}
+
+/// CSV with `currency,code,text,next_update,provider,table_no,effective_date,time_fetched` and
+/// `rate,code_from,code_to,rate,time_last_update,next_update,provider,time_fetched` rows
+pub fn to_csv(snapshot: &Snapshot) -> std::result::Result<String, String> {
+    let mut writer = csv::WriterBuilder::new()
+        .flexible(true)
+        .from_writer(Vec::new());
+    for currency in &snapshot.currencies {
+        writer
+            .write_record([
+                "currency",
+                &currency.code,
+                &currency.text,
+                &currency.next_update.to_string(),
+                &currency.provider,
+                currency.table_no.as_deref().unwrap_or_default(),
+                currency.effective_date.as_deref().unwrap_or_default(),
+                &currency.time_fetched.to_string(),
+            ])
+            .map_err(|e| e.to_string())?;
+    }
+    for rate in &snapshot.exchange_rates {
+        writer
+            .write_record([
+                "rate",
+                &rate.code_from,
+                &rate.code_to,
+                &rate.rate,
+                &rate.time_last_update.to_string(),
+                &rate.next_update.to_string(),
+                &rate.provider,
+                &rate.time_fetched.to_string(),
+            ])
+            .map_err(|e| e.to_string())?;
+    }
+    let bytes = writer.into_inner().map_err(|e| e.to_string())?;
+    String::from_utf8(bytes).map_err(|e| e.to_string())
+}
+
+/// Parses CSV written by [`to_csv`], returns all invalid rows with their line numbers on error
+pub fn parse_csv(text: &str) -> std::result::Result<Snapshot, Vec<String>> {
+    let mut snapshot = Snapshot {
+        currencies: Vec::new(),
+        exchange_rates: Vec::new(),
+    };
+    let mut errors: Vec<String> = Vec::new();
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    for record in reader.records() {
+        let record = record.map_err(|e| vec![e.to_string()])?;
+        let line_no = record.position().map(|pos| pos.line()).unwrap_or_default();
+        let field = |i: usize| record.get(i).unwrap_or_default().to_string();
+        let optional = |i: usize| Some(field(i)).filter(|value| !value.is_empty());
+        let time = |i: usize| field(i).parse::<u64>().ok();
+        // Fetch time is missing in snapshots exported before it was added
+        let time_fetched = match record.get(7) {
+            None | Some("") => Some(0),
+            Some(_) => time(7),
+        };
+        let fields = record.len();
+        match (record.get(0), time(3), time(4), time(5), time_fetched) {
+            _ if fields != 7 && fields != 8 => {
+                errors.push(format!("line {}: expected currency or rate row", line_no))
+            }
+            (Some("currency"), Some(next_update), _, _, Some(time_fetched)) => {
+                snapshot.currencies.push(CurrencyRow {
+                    code: field(1),
+                    text: field(2),
+                    next_update,
+                    provider: field(4),
+                    table_no: optional(5),
+                    effective_date: optional(6),
+                    time_fetched,
+                })
+            }
+            (Some("rate"), _, Some(time_last_update), Some(next_update), Some(time_fetched)) => {
+                snapshot.exchange_rates.push(RateRow {
+                    code_from: field(1),
+                    code_to: field(2),
+                    rate: field(3),
+                    time_last_update,
+                    next_update,
+                    provider: field(6),
+                    time_fetched,
+                })
+            }
+            (Some("currency" | "rate"), ..) => {
+                errors.push(format!("line {}: invalid update time", line_no))
+            }
+            _ => errors.push(format!("line {}: expected currency or rate row", line_no)),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    validate(snapshot)
+}
+
+pub fn parse_json(text: &str) -> std::result::Result<Snapshot, Vec<String>> {
+    validate(serde_json::from_str(text).map_err(|e| vec![e.to_string()])?)
+}
+
+/// Checks codes and rates, bringing them to form they are cached in
+fn validate(mut snapshot: Snapshot) -> std::result::Result<Snapshot, Vec<String>> {
+    let mut errors: Vec<String> = Vec::new();
+    for currency in &mut snapshot.currencies {
+        match parse_code(&currency.code) {
+            Some(code) => currency.code = code,
+            None => errors.push(format!("invalid code {}", currency.code)),
+        }
+    }
+    for rate in &mut snapshot.exchange_rates {
+        match (parse_code(&rate.code_from), parse_code(&rate.code_to)) {
+            (Some(code_from), Some(code_to)) => {
+                rate.code_from = code_from;
+                rate.code_to = code_to;
+            }
+            _ => {
+                errors.push(format!("invalid code {} or {}", rate.code_from, rate.code_to));
+                continue;
+            }
+        }
+        match parse_rate(&rate.rate) {
+            Some(value) => rate.rate = value.to_string(),
+            None => errors.push(format!(
+                "{} to {}: rate {} is not a positive number",
+                rate.code_from, rate.code_to, rate.rate
+            )),
+        }
+    }
+    if errors.is_empty() {
+        Ok(snapshot)
+    } else {
+        Err(errors)
+    }
+}
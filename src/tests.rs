@@ -1,7 +1,11 @@
 use crate::{cache::get_api_key, *};
-use std::sync::Once;
+use rust_decimal::Decimal;
+use std::sync::{Once, RwLock};
 
 static INIT: Once = Once::new();
+/// Held for writing while TTL is set in shared test cache, and for reading by tests relying on
+/// rates past provider's next update being stale
+static TTL_LOCK: RwLock<()> = RwLock::new(());
 
 fn setup_test() {
     INIT.call_once(|| {
@@ -10,6 +14,9 @@ fn setup_test() {
         path.push("testCurrencyCache.db");
 
         std::env::set_var(config::CACHE_LOCATION_ENV_NAME, &path);
+        std::env::set_var(config::PROVIDER_ENV_NAME, providers::mock::ID);
+        // Nothing listens there, so ExchangeRate-API fails like unreachable provider
+        std::env::set_var(config::REST_ENDPOINT_ENV_NAME, "http://127.0.0.1:9/");
         if path.exists() {
             std::fs::remove_file(path).expect("Something went wrong when removing test cache");
         }
@@ -19,12 +26,20 @@ fn setup_test() {
         cache::add_code(["PLN".to_string(), "Polish zloty".to_string()])
             .expect("Something went wrong when adding code");
         requests::get_currencies().expect("Something went wrong when getting currencies");
-        requests::get_rates(&"PLN".to_string()).expect("Something went wrong when getting rates");
+        requests::get_rates("PLN").expect("Something went wrong when getting rates");
 
-        let mut rates: std::collections::HashMap<String, serde_json::Value> =
+        let mut rates: std::collections::HashMap<String, Decimal> =
             std::collections::HashMap::new();
-        rates.insert("USD".to_string(), serde_json::json!(0.2546));
-        cache::add_rates(99710201602, &"PLN".to_string(), &rates).expect("Error seting rates");
+        rates.insert("USD".to_string(), Decimal::new(2546, 4));
+        let rates = providers::Rates {
+            base_code: "PLN".to_string(),
+            time_last_update_unix: 99710115202,
+            time_next_update_unix: 99710201602,
+            effective_date: None,
+            table_no: None,
+            conversion_rates: rates,
+        };
+        cache::add_rates(providers::mock::ID, &rates).expect("Error seting rates");
     });
 }
 #[test]
@@ -36,11 +51,351 @@ fn test_cache_get_api_key() {
         "testKey"
     );
 }
+#[test]
+fn test_cache_migrate() {
+    // Layout of caches created before schema versioning
+    let mut conn = rusqlite::Connection::open_in_memory().expect("Error opening database");
+    conn.execute_batch(
+        "
+        CREATE TABLE config (name TEXT PRIMARY KEY, value TEXT NOT NULL);
+        CREATE TABLE currencies (code TEXT PRIMARY KEY, text TEXT NOT NULL,
+            next_update TIME NOT NULL);
+        CREATE TABLE exchange_rates (code_from TEXT NOT NULL, code_to TEXT NOT NULL,
+            rate TEXT NOT NULL, PRIMARY KEY (code_from, code_to));
+        INSERT INTO config VALUES ('API_KEY', 'oldKey');
+        INSERT INTO currencies VALUES ('PLN', 'Polish zloty', 99710201602);
+        INSERT INTO exchange_rates VALUES ('PLN', 'EUR', '0.2325');
+        ",
+    )
+    .expect("Error creating old cache");
+    cache::migrate(&mut conn).expect("Error migrating cache");
+    // Running again on up to date cache does nothing
+    cache::migrate(&mut conn).expect("Error migrating cache again");
+
+    let version: u32 = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .expect("Error reading version");
+    assert_eq!(version, cache::SCHEMA_VERSION);
+    let key: String = conn
+        .query_row("SELECT value FROM config WHERE name = 'API_KEY'", [], |row| row.get(0))
+        .expect("Error reading api key");
+    assert_eq!(key, "oldKey");
+    let rate: (String, u64, String) = conn
+        .query_row(
+            "SELECT rate, next_update, provider FROM exchange_rates WHERE code_from = 'PLN'",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .expect("Error reading migrated rate");
+    assert_eq!(rate, ("0.2325".to_string(), 0, String::new()));
+    let historical: u64 = conn
+        .query_row("SELECT COUNT(*) FROM historical_rates", [], |row| row.get(0))
+        .expect("Error reading historical rates");
+    assert_eq!(historical, 0);
+}
+
+#[test]
+fn test_cache_concurrent_writers() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    setup_test();
+    // Every cache call opens own connection, like separate processes sharing cache
+    let writers: Vec<_> = ["ISK", "HUF", "CZK", "RON"]
+        .into_iter()
+        .map(|code| {
+            std::thread::spawn(move || {
+                cache::add_code([code.to_string(), String::new()])?;
+                for n in 1..=20 {
+                    let rates = providers::Rates {
+                        base_code: code.to_string(),
+                        time_last_update_unix: 9710000000 + n,
+                        time_next_update_unix: 9710201602 + n,
+                        effective_date: None,
+                        table_no: None,
+                        conversion_rates: [("PLN".to_string(), Decimal::new(n as i64, 2))].into(),
+                    };
+                    cache::add_rates(providers::mock::ID, &rates)?;
+                    cache::list_rates("PLN")?;
+                }
+                cache::get_rate(code, "PLN")
+            })
+        })
+        .collect();
+    for writer in writers {
+        assert_eq!(writer.join().expect("Writer panicked"), Ok("0.2".to_string()));
+    }
+    let cache = cache::Cache::open().expect("Error opening cache");
+    assert_eq!(cache.get_rate_history("RON", "PLN", 0).expect("Error").len(), 20);
+
+    // Only first of concurrent refreshes of same base does the work, others wait for it
+    let refreshed = std::sync::Arc::new(AtomicUsize::new(0));
+    let refreshes: Vec<_> = (0..4)
+        .map(|_| {
+            let refreshed = refreshed.clone();
+            std::thread::spawn(move || {
+                cache::with_refresh_lock("ISK", || -> rusqlite::Result<()> {
+                    if refreshed.load(Ordering::SeqCst) == 0 {
+                        std::thread::sleep(std::time::Duration::from_millis(100));
+                        refreshed.fetch_add(1, Ordering::SeqCst);
+                    }
+                    Ok(())
+                })
+            })
+        })
+        .collect();
+    for refresh in refreshes {
+        assert_eq!(refresh.join().expect("Refresh panicked"), Ok(()));
+    }
+    assert_eq!(refreshed.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_cache_atomic_refresh() {
+    setup_test();
+    cache::add_code(["MXN".to_string(), "Mexican peso".to_string()]).expect("Error adding code");
+    let table = |n: u64| providers::Rates {
+        base_code: "MXN".to_string(),
+        time_last_update_unix: 9710000000 + n,
+        time_next_update_unix: 9710201602 + n,
+        effective_date: None,
+        table_no: None,
+        conversion_rates: (0..40)
+            .map(|i| (format!("Z{:02}", i), Decimal::new(n as i64, 0)))
+            .collect(),
+    };
+    cache::add_rates(providers::mock::ID, &table(1)).expect("Error adding rates");
+    let writer = std::thread::spawn(move || {
+        for n in 2..=20 {
+            cache::add_rates(providers::mock::ID, &table(n))?;
+        }
+        Ok::<(), rusqlite::Error>(())
+    });
+    // Every listing comes from single refresh, never mix of two
+    while !writer.is_finished() {
+        let rates = cache::list_rates("MXN").expect("Error listing rates");
+        assert_eq!(rates.len(), 40);
+        assert!(rates.iter().all(|rate| rate[1] == rates[0][1]), "{:?}", rates);
+    }
+    assert_eq!(writer.join().expect("Writer panicked"), Ok(()));
+    assert_eq!(cache::get_rate("MXN", "Z39").expect("Error getting rate"), "20");
+    let cache = cache::Cache::open().expect("Error opening cache");
+    assert_eq!(cache.get_next_update("MXN").expect("Error"), 9710201622);
+}
+
+#[test]
+fn test_cache_refresh_removes_pairs() {
+    setup_test();
+    cache::add_code(["ARS".to_string(), "Argentine peso".to_string()]).expect("Error adding code");
+    let table = |time: u64, codes: &[&str]| providers::Rates {
+        base_code: "ARS".to_string(),
+        time_last_update_unix: time,
+        time_next_update_unix: time + 86400,
+        effective_date: None,
+        table_no: None,
+        conversion_rates: codes
+            .iter()
+            .map(|code| (code.to_string(), Decimal::new(time as i64, 0)))
+            .collect(),
+    };
+    cache::add_rates(providers::mock::ID, &table(9710000000, &["BOB", "CLP", "PYG"]))
+        .expect("Error adding rates");
+    cache::add_rates(providers::file::ID, &table(9700000000, &["BOB"])).expect("Error");
+    // Only new table is listed, with its own time
+    let rates = cache::list_rates("ARS").expect("Error listing rates");
+    assert_eq!(rates, [["BOB".to_string(), "9700000000".to_string()]]);
+    let cache = cache::Cache::open().expect("Error opening cache");
+    assert_eq!(cache.get_rates_time("ARS").expect("Error"), 9700000000);
+    // Removed pairs stay in time series
+    let history = cache.get_rate_history("ARS", "CLP", 0).expect("Error");
+    assert_eq!(history.len(), 1);
+}
+
+#[test]
+fn test_cache_fetch_time() {
+    setup_test();
+    let cache = cache::Cache::open().expect("Error opening cache");
+    cache::add_code(["KRW".to_string(), "South Korean won".to_string()]).expect("Error");
+    // Past next update, so only fresh when fetch time decides
+    let rates = providers::Rates {
+        base_code: "KRW".to_string(),
+        time_last_update_unix: 1000,
+        time_next_update_unix: 2000,
+        effective_date: None,
+        table_no: None,
+        conversion_rates: [("KPW".to_string(), Decimal::new(67, 2))].into(),
+    };
+    cache::add_rates(providers::mock::ID, &rates).expect("Error adding rates");
+    let now = config::get_current_time();
+    assert!(cache.get_rates_fetch_time("KRW").expect("Error") + 60 > now);
+    assert!(cache.get_rate_fetch_time("KRW", "KPW").expect("Error") + 60 > now);
+
+    let is_listed = |fetched_since: Option<u64>| {
+        cache
+            .list_fresh_rates(now, fetched_since)
+            .expect("Error listing fresh rates")
+            .iter()
+            .any(|rate| rate[0] == "KRW")
+    };
+    assert!(!is_listed(None));
+    assert!(is_listed(Some(now - 60)));
+    assert!(!is_listed(Some(now + 60)));
+}
+
+#[test]
+fn test_exchange_prefetch() {
+    setup_test();
+    let _ttl = TTL_LOCK.read().unwrap_or_else(|e| e.into_inner());
+    let cache = cache::Cache::open().expect("Error opening cache");
+    cache::add_code(["THB".to_string(), "Thai baht".to_string()]).expect("Error");
+    cache::add_code(["VND".to_string(), "Vietnamese dong".to_string()]).expect("Error");
+    let rates = |code: &str, time_next_update_unix: u64| providers::Rates {
+        base_code: code.to_string(),
+        time_last_update_unix: 1000,
+        time_next_update_unix,
+        effective_date: None,
+        table_no: None,
+        conversion_rates: [("LAK".to_string(), Decimal::new(590, 0))].into(),
+    };
+    cache::add_rates(providers::mock::ID, &rates("THB", u64::MAX / 2)).expect("Error");
+    // Stale, and mock provider can't fetch VND
+    cache::add_rates(providers::mock::ID, &rates("VND", 2000)).expect("Error");
+
+    let codes = ["THB", "VND", "QQQ"].map(String::from);
+    let results = exchange::prefetch(&cache, &codes).expect("Error prefetching");
+    assert_eq!(results.len(), 3);
+    assert!(matches!(results[0], (ref code, exchange::Prefetch::Fresh) if code == "THB"));
+    assert!(matches!(results[1].1, exchange::Prefetch::Failed(Error::UnknownCode(_))));
+    assert!(matches!(results[2].1, exchange::Prefetch::Failed(Error::UnknownCode(_))));
+}
+
+#[test]
+fn test_exchange_provenance() {
+    setup_test();
+    let cache = cache::Cache::open().expect("Error opening cache");
+    for code in ["KES", "TZS", "UGX"] {
+        cache::add_code([code.to_string(), code.to_string()]).expect("Error");
+    }
+    let rates = |code_from: &str, code_to: &str, time_last_update_unix: u64| providers::Rates {
+        base_code: code_from.to_string(),
+        time_last_update_unix,
+        time_next_update_unix: u64::MAX / 2,
+        effective_date: None,
+        table_no: None,
+        conversion_rates: [(code_to.to_string(), Decimal::new(2, 0))].into(),
+    };
+    // 2024-03-11 00:00 and 2024-03-10 00:00 UTC
+    cache::add_rates(providers::mock::ID, &rates("KES", "TZS", 1710115200)).expect("Error");
+    cache::add_rates(providers::mock::ID, &rates("TZS", "UGX", 1710028800)).expect("Error");
+    let provenance = cache.get_rate_provenance("KES", "TZS").expect("Error");
+    assert_eq!(provenance.provider, providers::mock::ID);
+    assert_eq!(provenance.time_last_update, 1710115200);
+    assert!(provenance.time_fetched + 60 > config::get_current_time());
+
+    let conversion = exchange::convert_value(&cache, "KES", "TZS", "10").expect("Error");
+    assert_eq!(conversion.time_last_update, 1710115200);
+    assert_eq!(conversion.time_fetched, provenance.time_fetched);
+    // Derived rate is as old as its oldest rate
+    let conversion = exchange::convert_value(&cache, "KES", "UGX", "10").expect("Error");
+    assert_eq!(conversion.path, ["KES", "TZS", "UGX"]);
+    assert_eq!(conversion.time_last_update, 1710028800);
+}
+
+#[test]
+fn test_output_formats() {
+    setup_test();
+    let records = [output::RateRecord {
+        code_from: "PLN".to_string(),
+        code_to: "EUR".to_string(),
+        rate: "0.2325".to_string(),
+    }];
+    assert_eq!(output::format_records(output::Format::Text, &records), None);
+    assert_eq!(
+        output::format_records(output::Format::Csv, &records).as_deref(),
+        Some("code_from,code_to,rate\nPLN,EUR,0.2325\n")
+    );
+    assert_eq!(
+        output::format_records(output::Format::Tsv, &records).as_deref(),
+        Some("code_from\tcode_to\trate\nPLN\tEUR\t0.2325\n")
+    );
+    let json = output::format_records(output::Format::Json, &records).expect("No JSON");
+    let value: serde_json::Value = serde_json::from_str(&json).expect("Invalid JSON");
+    assert_eq!(value[0]["rate"], "0.2325");
+
+    let cache = cache::Cache::open().expect("Error opening cache");
+    let conversion = exchange::convert_value(&cache, "PLN", "EUR", "100.50").expect("Error");
+    let record = output::ConversionRecord::from(&conversion);
+    let json = output::format_record(output::Format::Json, &record).expect("No JSON");
+    let value: serde_json::Value = serde_json::from_str(&json).expect("Invalid JSON");
+    // Exact decimals as strings, separate from formatted money
+    assert_eq!(value["amount"], "100.50");
+    assert_eq!(value["value"], "23.366250");
+    assert_eq!(value["code_to"], "EUR");
+    assert_eq!(value["path"], serde_json::json!([]));
+    let csv = output::format_record(output::Format::Csv, &record).expect("No CSV");
+    assert_eq!(csv.lines().count(), 2);
+    assert!(csv.lines().next().unwrap_or_default().starts_with("code_from,code_to,amount"));
+}
+
+#[test]
+fn test_exchange_convert_values() {
+    setup_test();
+    let cache = cache::Cache::open().expect("Error opening cache");
+    for code in ["MYR", "SGD", "IDR"] {
+        cache::add_code([code.to_string(), code.to_string()]).expect("Error");
+    }
+    let rates = providers::Rates {
+        base_code: "MYR".to_string(),
+        time_last_update_unix: 1000,
+        time_next_update_unix: u64::MAX / 2,
+        effective_date: None,
+        table_no: None,
+        conversion_rates: [
+            ("SGD".to_string(), Decimal::new(29, 2)),
+            ("IDR".to_string(), Decimal::new(3350, 0)),
+        ]
+        .into(),
+    };
+    cache::add_rates(providers::mock::ID, &rates).expect("Error adding rates");
+
+    let codes_to = ["SGD", "IDR"].map(String::from);
+    let conversions = exchange::convert_values(&cache, "MYR", &codes_to, "10").expect("Error");
+    let values: Vec<(&str, String)> = conversions
+        .iter()
+        .map(|conversion| (conversion.code_to.as_str(), conversion.value.to_string()))
+        .collect();
+    assert_eq!(values, [("SGD", "2.90".to_string()), ("IDR", "33500".to_string())]);
+
+    let codes_to = ["SGD", "QQQ"].map(String::from);
+    assert!(matches!(
+        exchange::convert_values(&cache, "MYR", &codes_to, "10"),
+        Err(Error::UnknownCode(code)) if code == "QQQ"
+    ));
+}
+
+#[test]
+fn test_exchange_convert_values_fetch_once() {
+    setup_test();
+    // PLN rates are cached before refresh is set, so no other test refetches them after it
+    let mut cache = cache::Cache::open().expect("Error opening cache");
+    for code in ["USD", "GBP"] {
+        cache::add_code([code.to_string(), String::new()]).expect("Error adding code");
+    }
+    exchange::convert_value(&cache, "PLN", "EUR", "1").expect("Error while converting");
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    cache.set_overrides(config::Overrides { refresh: true, ..Default::default() });
+
+    let codes_to = ["EUR", "USD", "GBP"].map(String::from);
+    let fetches = providers::mock::fetch_count();
+    let conversions = exchange::convert_values(&cache, "PLN", &codes_to, "10").expect("Error");
+    assert_eq!(providers::mock::fetch_count() - fetches, 1);
+    assert!(conversions.iter().all(|conversion| conversion.stale.is_none()));
+    assert!(conversions.iter().all(|conversion| conversion.source == "Mock data"));
+}
+
 #[test]
 fn test_cache_check_code() {
     setup_test();
 
-    assert!(cache::check_code(&"PLN".to_string()).expect("Something went wrong when getting code"));
+    assert!(cache::check_code("PLN").expect("Something went wrong when getting code"));
 }
 
 #[test]
@@ -48,7 +403,7 @@ fn test_cache_get_rates() {
     setup_test();
 
     assert_eq!(
-        cache::get_rate(&"PLN".to_string(), &"USD".to_string()).expect("Error getting rates"),
+        cache::get_rate("PLN", "USD").expect("Error getting rates"),
         "0.2546"
     );
 }
@@ -58,16 +413,18 @@ fn test_cache_check_exchange() {
     setup_test();
 
     assert!(
-        cache::check_exchange(&"PLN".to_string(), &"USD".to_string()).expect("Error while checking exchange")
+        cache::check_exchange("PLN", "USD").expect("Error while checking exchange")
     );
 }
 
 #[test]
 fn test_exchange_convert_value() {
     setup_test();
-    let result = exchange::convert_value(&"PLN".to_string(), &"EUR".to_string(), &"100".to_string());
+    let cache = cache::Cache::open().expect("Error opening cache");
+    let result = exchange::convert_value(&cache, "PLN", "EUR", "100")
+        .expect("Error while converting");
     assert_eq!(
-        result.rate, "0.2325".to_string()
+        result.rate.to_string(), "0.2325".to_string()
     );
     assert_eq!(
         result.from, "100zł".to_string()
@@ -75,8 +432,670 @@ fn test_exchange_convert_value() {
     assert_eq!(
         result.to, "€23,25".to_string()
     );
+    assert_eq!(result.source, "Mock data");
     
 }
 
 
 
+#[test]
+fn test_exchange_list_rates() {
+    setup_test();
+    let cache = cache::Cache::open().expect("Error opening cache");
+    let rates = exchange::list_rates(&cache, "EUR").expect("Error while listing rates").rates;
+    assert!(rates.contains(&("PLN".to_string(), Decimal::new(4_3011, 4))));
+}
+
+#[test]
+fn test_exchange_historical_rates() {
+    setup_test();
+    let cache = cache::Cache::open().expect("Error opening cache");
+    let next_update = cache.get_rate_info("PLN", "EUR").expect("Error getting rate info").1;
+    let result = exchange::convert_value_at(&cache, "PLN", "EUR", "100", "2024-03-11")
+        .expect("Error while converting with historical rate");
+    assert_eq!(result.rate.to_string(), "0.2325");
+    assert_eq!(result.date, Some("2024-03-11".to_string()));
+    assert!(cache.check_historical_exchange("PLN", "EUR", "2024-03-11").expect("Error"));
+    // Dated conversion doesn't touch current rates
+    assert_eq!(
+        cache.get_rate_info("PLN", "EUR").expect("Error getting rate info").1,
+        next_update
+    );
+    assert!(matches!(
+        exchange::convert_value_at(&cache, "PLN", "EUR", "100", "2999-01-01"),
+        Err(Error::InvalidDate(_))
+    ));
+    assert!(matches!(
+        exchange::list_historical_rates(&cache, "PLN", "11.03.2024"),
+        Err(Error::InvalidDate(_))
+    ));
+}
+
+#[test]
+fn test_exchange_unpadded_date() {
+    setup_test();
+    let cache = cache::Cache::open().expect("Error opening cache");
+    assert_eq!(exchange::check_date("2024-3-11").expect("Error"), "2024-03-11");
+    let result = exchange::convert_value_at(&cache, "PLN", "EUR", "100", "2024-3-8")
+        .expect("Error while converting with unpadded date");
+    assert_eq!(result.date, Some("2024-03-08".to_string()));
+    // Cached under same key as padded date
+    assert!(cache.check_historical_exchange("PLN", "EUR", "2024-03-08").expect("Error"));
+    assert!(!cache.check_historical_exchange("PLN", "EUR", "2024-3-8").expect("Error"));
+
+    // Unreachable endpoint, so only building of request is tested
+    use providers::Provider;
+    let api = providers::exchangerate_api::ExchangeRateApi::new(
+        "http://127.0.0.1:9/".to_string(),
+        "key".to_string(),
+    );
+    assert!(matches!(
+        api.get_historical_rates("PLN", "2024-3-11"),
+        Err(Error::Network(_))
+    ));
+}
+
+#[test]
+fn test_exchange_rate_history() {
+    setup_test();
+    let cache = cache::Cache::open().expect("Error opening cache");
+    cache::add_code(["CHF".to_string(), "Swiss franc".to_string()]).expect("Error adding code");
+    let history = [(1709856000, Decimal::new(45103, 4)), (1710115200, Decimal::new(44962, 4))];
+    for (time, rate) in history {
+        let rates = providers::Rates {
+            base_code: "CHF".to_string(),
+            time_last_update_unix: time,
+            time_next_update_unix: time + 86400,
+            effective_date: None,
+            table_no: None,
+            conversion_rates: [("PLN".to_string(), rate)].into(),
+        };
+        cache::add_pair_rates(providers::mock::ID, &rates).expect("Error adding rates");
+    }
+    // Latest rate is still looked up directly, older one is kept in history
+    assert_eq!(cache::get_rate("CHF", "PLN").expect("Error getting rate"), "4.4962");
+    let history = exchange::rate_history(&cache, "CHF", "PLN", None)
+        .expect("Error getting history");
+    let rates: Vec<(u64, String)> = history
+        .iter()
+        .map(|(time, rate, _)| (*time, rate.to_string()))
+        .collect();
+    assert_eq!(
+        rates,
+        [(1709856000, "4.5103".to_string()), (1710115200, "4.4962".to_string())]
+    );
+    assert_eq!(history[0].2, "Mock data");
+    let since = exchange::rate_history(&cache, "CHF", "PLN", Some("2024-03-09")).expect("Error");
+    assert_eq!(since.len(), 1);
+    assert_eq!(config::format_time(since[0].0), "2024-03-11 00:00");
+}
+
+#[test]
+fn test_snapshot_merge() {
+    setup_test();
+    let rate = |rate: &str, time_last_update: u64| cache::RateRow {
+        code_from: "NOK".to_string(),
+        code_to: "dkk".to_string(),
+        rate: rate.to_string(),
+        time_last_update,
+        next_update: 9710201602,
+        provider: providers::mock::ID.to_string(),
+        time_fetched: time_last_update + 60,
+    };
+    let snapshot = snapshot::Snapshot {
+        currencies: vec![cache::CurrencyRow {
+            code: "nok".to_string(),
+            text: "Norwegian, krone".to_string(),
+            next_update: 9710201602,
+            provider: providers::mock::ID.to_string(),
+            table_no: None,
+            effective_date: None,
+            time_fetched: 9710115262,
+        }],
+        exchange_rates: vec![rate("0.6400", 9710115202)],
+    };
+    // Codes and rates are brought to cached form, quoted text survives roundtrip
+    let csv = snapshot::to_csv(&snapshot).expect("Error writing CSV");
+    let parsed = snapshot::parse_csv(&csv).expect("Error parsing CSV");
+    assert_eq!(parsed.currencies[0].code, "NOK");
+    assert_eq!(parsed.currencies[0].text, "Norwegian, krone");
+    assert_eq!(parsed.exchange_rates[0].code_to, "DKK");
+    assert_eq!(parsed.exchange_rates[0].rate, "0.64");
+    assert_eq!(parsed.exchange_rates[0].time_fetched, 9710115262);
+
+    assert_eq!(cache::merge_rows(&parsed.currencies, &parsed.exchange_rates), Ok((1, 1)));
+    assert_eq!(cache::merge_rows(&parsed.currencies, &parsed.exchange_rates), Ok((0, 0)));
+    // Imported rates keep time they were fetched at
+    let cache = cache::Cache::open().expect("Error opening cache");
+    assert_eq!(cache.get_rates_fetch_time("NOK"), Ok(9710115262));
+    assert_eq!(cache.get_rate_fetch_time("NOK", "DKK"), Ok(9710115262));
+    // Older rate doesn't replace cached one, newer does
+    assert_eq!(cache::merge_rows(&[], &[rate("0.62", 9710000000)]), Ok((0, 0)));
+    assert_eq!(cache::get_rate("NOK", "DKK").expect("Error getting rate"), "0.64");
+    assert_eq!(cache::merge_rows(&[], &[rate("0.65", 9710200000)]), Ok((0, 1)));
+    assert_eq!(cache::get_rate("NOK", "DKK").expect("Error getting rate"), "0.65");
+
+    let errors = snapshot::parse_csv("rate,NOK,DKK,-1,0,0,mock\nrates,NOK\n").unwrap_err();
+    assert_eq!(errors, ["line 2: expected currency or rate row"]);
+    let errors = snapshot::parse_csv("rate,NOK,DKK,-1,0,0,mock\n").unwrap_err();
+    assert_eq!(errors, ["NOK to DKK: rate -1 is not a positive number"]);
+    let path = std::env::temp_dir().join("missingSnapshot.json");
+    assert!(matches!(
+        snapshot::import(&path),
+        Err(Error::Snapshot { path: error_path, .. }) if error_path == path.display().to_string()
+    ));
+    // Snapshots exported without fetch times are still accepted
+    let parsed = snapshot::parse_csv("rate,NOK,DKK,0.64,0,0,mock\n").expect("Error parsing CSV");
+    assert_eq!(parsed.exchange_rates[0].time_fetched, 0);
+}
+
+#[test]
+fn test_exchange_cross_rate() {
+    setup_test();
+    let cache = cache::Cache::open().expect("Error opening cache");
+    for code in ["SEK", "XAU", "XAG"] {
+        cache::add_code([code.to_string(), String::new()]).expect("Error adding code");
+    }
+    let rates = providers::Rates {
+        base_code: "SEK".to_string(),
+        time_last_update_unix: 9710115202,
+        time_next_update_unix: 9710201602,
+        effective_date: None,
+        table_no: None,
+        conversion_rates: [
+            ("SEK".to_string(), Decimal::ONE),
+            ("XAU".to_string(), Decimal::new(10218, 4)),
+            ("XAG".to_string(), Decimal::new(6621, 4)),
+        ]
+        .into(),
+    };
+    cache::add_rates(providers::mock::ID, &rates).expect("Error adding rates");
+    // Mock provider has no XAU rates, so rate can only come from cached SEK rates
+    let result = exchange::convert_value(&cache, "XAU", "XAG", "100")
+        .expect("Error while converting");
+    assert_eq!(result.rate.to_string(), "0.64797416");
+    assert_eq!(result.path, ["XAU", "SEK", "XAG"]);
+    assert_eq!(result.source, "Mock data");
+    assert!(!cache::check_exchange("XAU", "XAG").expect("Error checking exchange"));
+    let inverse = exchange::convert_value(&cache, "XAG", "SEK", "1")
+        .expect("Error while converting");
+    assert_eq!(inverse.rate.to_string(), "1.5103459");
+}
+
+#[test]
+fn test_exchange_rate_path() {
+    setup_test();
+    let cache = cache::Cache::open().expect("Error opening cache");
+    // Chain CLF -> XPT -> XPD -> BOV, with first rate used inverted
+    for (base_code, code_to, rate) in [("XPT", "CLF", 2), ("XPT", "XPD", 4), ("XPD", "BOV", 3)] {
+        let rates = providers::Rates {
+            base_code: base_code.to_string(),
+            time_last_update_unix: 9710115202,
+            time_next_update_unix: 9710201602,
+            effective_date: None,
+            table_no: None,
+            conversion_rates: [(code_to.to_string(), Decimal::from(rate))].into(),
+        };
+        cache::add_pair_rates(providers::mock::ID, &rates).expect("Error adding rates");
+    }
+    let rate_path = exchange::find_rate_path(&cache, "CLF", "BOV", 3)
+        .expect("Error finding path")
+        .expect("Path should be found");
+    assert_eq!(rate_path.path, ["CLF", "XPT", "XPD", "BOV"]);
+    assert_eq!(rate_path.rate.to_string(), "6");
+    assert_eq!(rate_path.providers.len(), 3);
+    assert!(exchange::find_rate_path(&cache, "CLF", "BOV", 2).expect("Error").is_none());
+    // Derivable pair doesn't need fetching
+    assert!(!exchange::needs_fetch(&cache, "CLF", Some("BOV"), None).expect("Error"));
+    let inverse = exchange::find_rate_path(&cache, "BOV", "XPD", 1)
+        .expect("Error finding path")
+        .expect("Inverted rate should be found");
+    assert_eq!(inverse.rate.to_string(), "0.33333333");
+}
+
+#[test]
+fn test_exchange_freshness() {
+    setup_test();
+    let mut cache = cache::Cache::open().expect("Error opening cache");
+    cache::add_code(["GHS".to_string(), "Ghanaian cedi".to_string()]).expect("Error");
+    cache::add_code(["NGN".to_string(), "Nigerian naira".to_string()]).expect("Error");
+    let rates = |code: &str, time_next_update_unix: u64| providers::Rates {
+        base_code: code.to_string(),
+        time_last_update_unix: 1000,
+        time_next_update_unix,
+        effective_date: None,
+        table_no: None,
+        conversion_rates: [("XOF".to_string(), Decimal::new(50, 0))].into(),
+    };
+    // GHS is fresh until provider's next update, NGN is past it
+    cache::add_rates(providers::mock::ID, &rates("GHS", u64::MAX / 2)).expect("Error");
+    cache::add_rates(providers::mock::ID, &rates("NGN", 2000)).expect("Error");
+    let needs_fetch = |cache: &cache::Cache, code: &str| {
+        exchange::needs_fetch(cache, code, None, None).expect("Error checking freshness")
+    };
+    {
+        let _ttl = TTL_LOCK.read().unwrap_or_else(|e| e.into_inner());
+        assert!(!needs_fetch(&cache, "GHS"));
+        assert!(needs_fetch(&cache, "NGN"));
+    }
+    // Maximum age decides instead of next update, cutoff moves with time
+    let overrides = config::Overrides { max_age: Some(3600), ..Default::default() };
+    cache.set_overrides(overrides.clone());
+    assert!(!needs_fetch(&cache, "NGN"));
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    cache.set_overrides(config::Overrides { max_age: Some(0), ..Default::default() });
+    assert!(needs_fetch(&cache, "GHS"));
+    // Refresh refetches rates fetched before it was set, but not ones fetched after
+    cache.set_overrides(config::Overrides { refresh: true, ..Default::default() });
+    assert!(needs_fetch(&cache, "GHS"));
+    cache::add_rates(providers::mock::ID, &rates("GHS", u64::MAX / 2)).expect("Error");
+    assert!(!needs_fetch(&cache, "GHS"));
+    // Other handles are not affected
+    let other = cache::Cache::open().expect("Error opening cache");
+    assert!(!needs_fetch(&other, "GHS"));
+
+    // TTL in configuration applies when nothing is overridden
+    cache.set_overrides(config::Overrides::default());
+    let _ttl = TTL_LOCK.write().unwrap_or_else(|e| e.into_inner());
+    cache::set_config(config::TTL_CONFIG_NAME, "3600").expect("Error setting TTL");
+    let ttl = exchange::get_ttl(&cache);
+    let ngn_fresh = !needs_fetch(&cache, "NGN");
+    cache::set_config(config::TTL_CONFIG_NAME, "0").expect("Error unsetting TTL");
+    assert_eq!(ttl.expect("Error getting TTL"), Some(3600));
+    assert!(ngn_fresh);
+    assert!(needs_fetch(&cache, "NGN"));
+}
+
+#[test]
+fn test_exchange_stale_fallback() {
+    setup_test();
+    cache::add_code(["ETB".to_string(), "Ethiopian birr".to_string()]).expect("Error");
+    cache::add_code(["RWF".to_string(), "Rwandan franc".to_string()]).expect("Error");
+    let rates = providers::Rates {
+        base_code: "ETB".to_string(),
+        time_last_update_unix: 1000,
+        time_next_update_unix: 2000,
+        effective_date: None,
+        table_no: None,
+        conversion_rates: [("RWF".to_string(), Decimal::new(2250, 2))].into(),
+    };
+    cache::add_rates(providers::mock::ID, &rates).expect("Error adding rates");
+    // Cached rates come from provider outside chain, and provider in chain is unreachable
+    let mut overrides = config::Overrides {
+        providers: Some(vec![providers::exchangerate_api::ID.to_string()]),
+        ..Default::default()
+    };
+    let mut cache = cache::Cache::open_with(overrides.clone()).expect("Error opening cache");
+    let listed = exchange::list_rates(&cache, "ETB").expect("Error while listing rates");
+    assert_eq!(listed.stale, Some(1000));
+    assert_eq!(listed.rates, [("RWF".to_string(), Decimal::new(225, 1))]);
+    let conversion = exchange::convert_value(&cache, "ETB", "RWF", "2").expect("Error");
+    assert_eq!(conversion.stale, Some(1000));
+    assert_eq!(conversion.value, Decimal::new(45, 0));
+    // Rates older than allowed by --max-stale are not used
+    overrides.max_stale = Some(3600);
+    cache.set_overrides(overrides);
+    assert!(matches!(
+        exchange::list_rates(&cache, "ETB"),
+        Err(Error::StaleRates(code, _)) if code == "ETB"
+    ));
+    assert!(matches!(
+        exchange::convert_value(&cache, "ETB", "RWF", "2"),
+        Err(Error::StaleRates(code, _)) if code == "ETB"
+    ));
+}
+
+#[test]
+fn test_exchange_errors() {
+    setup_test();
+    let cache = cache::Cache::open().expect("Error opening cache");
+    assert!(matches!(
+        exchange::convert_value(&cache, "PLN", "XYZ", "100"),
+        Err(Error::UnknownCode(code)) if code == "XYZ"
+    ));
+    assert!(matches!(
+        exchange::convert_value(&cache, "PLN", "EUR", "abc"),
+        Err(Error::InvalidAmount(amount)) if amount == "abc"
+    ));
+    // Amount fits, but converted value doesn't
+    let max = Decimal::MAX.to_string();
+    assert!(matches!(
+        exchange::convert_value(&cache, "EUR", "PLN", &max),
+        Err(Error::InvalidAmount(amount)) if amount == max
+    ));
+}
+
+#[test]
+fn test_providers_get_provider_by_name() {
+    setup_test();
+    assert_eq!(
+        providers::get_provider_by_name(providers::mock::ID)
+            .expect("Error getting provider")
+            .name(),
+        "Mock data"
+    );
+    assert!(matches!(
+        providers::get_provider_by_name("unknown"),
+        Err(Error::UnknownProvider(_))
+    ));
+    // Display names don't need provider to be set up
+    assert_eq!(providers::get_display_name(providers::file::ID), "Rates file");
+    assert_eq!(providers::get_display_name("unknown"), "unknown");
+}
+
+#[test]
+fn test_providers_quota() {
+    let mut quota: providers::exchangerate_api::Quota = serde_json::from_str(include_str!(concat!(
+        ".",
+        crate::main_separator!(),
+        "mock_data",
+        crate::main_separator!(),
+        "quota.json"
+    )))
+    .expect("Error parsing quota");
+    assert_eq!(quota.plan_quota, 30000);
+    assert_eq!(quota.refresh_day_of_month, 17);
+    assert!(quota.is_low());
+    quota.requests_remaining = 3000;
+    assert!(!quota.is_low());
+}
+
+#[test]
+fn test_providers_exact_rates() {
+    let pair = |rate: &str| {
+        serde_json::from_str::<providers::exchangerate_api::PairConversion>(&format!(
+            r#"{{"base_code":"EUR","target_code":"XAU","time_last_update_unix":0,
+                "time_next_update_unix":0,"conversion_rate":{}}}"#,
+            rate
+        ))
+    };
+    let rate = pair("4.60e-7").expect("Error parsing rate").conversion_rate;
+    assert_eq!(rate.normalize().to_string(), "0.00000046");
+    let rate = pair("0.12345678901234567890").expect("Error parsing rate").conversion_rate;
+    assert_eq!(rate.to_string(), "0.12345678901234567890");
+    assert!(pair("0").is_err());
+    assert!(pair("-1.5").is_err());
+    assert!(pair(r#""abc""#).is_err());
+
+    // Zero rate would divide by zero when rebasing
+    assert!(matches!(
+        providers::nbp::parse_tables(
+            r#"[{"table":"A","no":"1","effectiveDate":"2024-03-08",
+                "rates":[{"currency":"x","code":"HUF","mid":0}]}]"#
+        ),
+        Err(Error::MalformedResponse(_))
+    ));
+}
+
+#[test]
+fn test_providers_ecb_reference_rates() {
+    let daily = providers::ecb::parse_reference_rates(include_str!(concat!(
+        ".",
+        crate::main_separator!(),
+        "mock_data",
+        crate::main_separator!(),
+        "eurofxref-daily.xml"
+    )))
+    .expect("Error parsing daily reference rates");
+    assert_eq!(daily.len(), 1);
+    assert_eq!(daily[0].date, "2024-03-08");
+    assert_eq!(daily[0].rates["EUR"].to_string(), "1");
+    assert_eq!(daily[0].rates["PLN"].to_string(), "4.3008");
+
+    let pln = daily[0].rebase("PLN").expect("Error rebasing rates");
+    assert_eq!(pln["PLN"].to_string(), "1");
+    assert_eq!(pln["EUR"].to_string(), "0.23251488");
+    assert_eq!(pln["USD"].to_string(), "0.25418527");
+    assert!(matches!(daily[0].rebase("XYZ"), Err(Error::UnknownCode(_))));
+
+    let hist = providers::ecb::parse_reference_rates(include_str!(concat!(
+        ".",
+        crate::main_separator!(),
+        "mock_data",
+        crate::main_separator!(),
+        "eurofxref-hist.xml"
+    )))
+    .expect("Error parsing historical reference rates");
+    let dates: Vec<&str> = hist.iter().map(|day| day.date.as_str()).collect();
+    assert_eq!(dates, ["2024-03-11", "2024-03-08", "2024-03-07"]);
+    // Weekend uses rates of preceding Friday
+    let day = providers::ecb::rates_on(hist, "2024-03-10").expect("Error finding rates on date");
+    assert_eq!(day.date, "2024-03-08");
+
+    // Friday rates are next published on Monday
+    assert_eq!(
+        providers::ecb::next_publication("2024-03-08").expect("Error getting next publication"),
+        config::parse_date("2024-03-11").unwrap() + 15 * 3600
+    );
+}
+
+#[test]
+fn test_config_parse_date() {
+    assert_eq!(config::parse_date("1970-01-01"), Some(0));
+    assert_eq!(config::parse_date("2024-03-11"), Some(1710115200));
+    assert_eq!(config::parse_date("2024-02-30"), None);
+    assert_eq!(config::parse_date("11.03.2024"), None);
+}
+
+#[test]
+fn test_config_parse_duration() {
+    assert_eq!(config::parse_duration("36h"), Some(129600));
+    assert_eq!(config::parse_duration("2d"), Some(172800));
+    assert_eq!(config::parse_duration("90"), Some(90));
+    assert_eq!(config::parse_duration("2x"), None);
+    assert_eq!(config::parse_duration("h"), None);
+    assert_eq!(config::format_duration(93784), "1d 2h");
+    assert_eq!(config::format_duration(3605), "1h");
+    assert_eq!(config::format_duration(3665), "1h 1m");
+    assert_eq!(config::format_duration(0), "0s");
+}
+
+#[test]
+fn test_error_is_unavailable() {
+    // Only unreachable providers let stale cached rates be used
+    assert!(Error::QuotaReached.is_unavailable());
+    assert!(!Error::InvalidKey.is_unavailable());
+    let failed = |second: Error| {
+        Error::AllProvidersFailed(vec![
+            ("a".to_string(), Error::QuotaReached),
+            ("b".to_string(), second),
+        ])
+    };
+    assert!(failed(Error::QuotaReached).is_unavailable());
+    assert!(!failed(Error::Api("unsupported-code".to_string())).is_unavailable());
+}
+
+#[test]
+fn test_providers_nbp_tables() {
+    let table_a = providers::nbp::parse_tables(include_str!(concat!(
+        ".",
+        crate::main_separator!(),
+        "mock_data",
+        crate::main_separator!(),
+        "nbp_table_a.json"
+    )))
+    .expect("Error parsing table A")
+    .remove(0);
+    assert_eq!(table_a.no, "049/A/NBP/2024");
+    assert_eq!(table_a.effective_date, "2024-03-08");
+
+    let pln = table_a.rebase("PLN").expect("Error rebasing table A");
+    assert_eq!(pln["PLN"].to_string(), "1");
+    assert_eq!(pln["EUR"].to_string(), "0.23251488");
+    // JSON API quotes every currency per unit, also ones XML tables quote per 100 units
+    assert_eq!(pln["HUF"].to_string(), "91.533181");
+    let eur = table_a.rebase("EUR").expect("Error rebasing table A");
+    assert_eq!(eur["PLN"].to_string(), "4.3008");
+    assert_eq!(eur["USD"].to_string(), "1.0930995");
+    assert!(matches!(table_a.rebase("AFN"), Err(Error::UnknownCode(_))));
+    assert_eq!(
+        table_a.next_publication().expect("Error getting next publication"),
+        config::parse_date("2024-03-11").unwrap() + 11 * 3600
+    );
+
+    let table_b = providers::nbp::parse_tables(include_str!(concat!(
+        ".",
+        crate::main_separator!(),
+        "mock_data",
+        crate::main_separator!(),
+        "nbp_table_b.json"
+    )))
+    .expect("Error parsing table B")
+    .remove(0);
+    assert_eq!(
+        table_b.next_publication().expect("Error getting next publication"),
+        config::parse_date("2024-03-13").unwrap() + 11 * 3600
+    );
+
+    let table_c = providers::nbp::parse_tables(include_str!(concat!(
+        ".",
+        crate::main_separator!(),
+        "mock_data",
+        crate::main_separator!(),
+        "nbp_table_c.json"
+    )))
+    .expect("Error parsing table C")
+    .remove(0);
+    // Buying USD for PLN uses ask, selling EUR uses bid
+    let pln = table_c.rebase("PLN").expect("Error rebasing table C");
+    assert_eq!(pln["USD"].to_string(), "0.25161031");
+    let eur = table_c.rebase("EUR").expect("Error rebasing table C");
+    assert_eq!(eur["PLN"].to_string(), "4.2588");
+    assert_eq!(eur["USD"].to_string(), "1.071558");
+    assert_eq!(eur["JPY"].to_string(), "157.31964");
+}
+
+#[test]
+fn test_providers_file() {
+    use providers::Provider;
+
+    let mut path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("src");
+    path.push("mock_data");
+
+    let csv = providers::FileProvider::new(path.join("rates.csv"));
+    let rates = csv.get_rates("PLN").expect("Error getting rates from CSV");
+    // Newest of duplicated pairs is used, inverted rate fills missing pair
+    assert_eq!(rates.conversion_rates["EUR"].to_string(), "0.2325");
+    assert_eq!(rates.conversion_rates["USD"].to_string(), "0.2546");
+    assert_eq!(rates.conversion_rates["CZK"].to_string(), "5.8754407");
+    assert_eq!(rates.effective_date, Some("2024-03-11".to_string()));
+    assert!(matches!(csv.get_rates("GBP"), Err(Error::UnknownCode(_))));
+    // Only rates published until end of requested day are used for history
+    let rates = csv
+        .get_historical_rates("PLN", "2024-03-08")
+        .expect("Error getting historical rates from CSV");
+    assert_eq!(rates.conversion_rates["EUR"].to_string(), "0.232");
+    assert!(!rates.conversion_rates.contains_key("USD"));
+    assert!(matches!(
+        csv.get_historical_rates("PLN", "2024-03-01"),
+        Err(Error::NoHistoricalRates(_))
+    ));
+
+    let json = providers::FileProvider::new(path.join("rates.json"));
+    let rates = json.get_rates("PLN").expect("Error getting rates from JSON");
+    assert_eq!(rates.conversion_rates["JPY"].to_string(), "37.393");
+    let currencies = json.get_currencies().expect("Error getting currencies from JSON");
+    assert_eq!(currencies.len(), 4);
+
+    let invalid = providers::FileProvider::new(path.join("rates_invalid.csv"));
+    match invalid.read() {
+        Err(Error::RatesFile { errors, .. }) => assert_eq!(
+            errors,
+            [
+                "line 3: invalid code EURO",
+                "line 4: rate -1 is not a positive number",
+                "line 5: invalid timestamp 11.03.2024",
+                "line 6: expected from,to,rate,timestamp",
+            ]
+        ),
+        _ => panic!("Invalid rates file should be rejected"),
+    }
+}
+
+#[test]
+fn test_requests_provider_fallback() {
+    setup_test();
+    // File provider fails without rates file set up, so mock provider answers
+    let chain = [providers::file::ID.to_string(), providers::mock::ID.to_string()];
+    assert_eq!(
+        requests::get_rates_from(&chain, "EUR").expect("Error while getting rates"),
+        providers::mock::ID
+    );
+    match requests::get_rates_from(&chain, "XYZ") {
+        Err(Error::AllProvidersFailed(errors)) => {
+            assert_eq!(errors.len(), 2);
+            assert!(matches!(errors[0].1, Error::RatesFile { .. }));
+            assert!(matches!(errors[1].1, Error::UnknownCode(_)));
+        }
+        _ => panic!("Unknown code should fail on every provider"),
+    }
+}
+
+#[test]
+fn test_requests_get_pair() {
+    setup_test();
+    assert_eq!(
+        requests::get_pair("EUR", "GBP").expect("Error while getting pair"),
+        providers::mock::ID
+    );
+    assert_eq!(
+        cache::get_rate("EUR", "GBP").expect("Error getting rate"),
+        "0.8517"
+    );
+    let cache = cache::Cache::open().expect("Error opening cache");
+    assert_eq!(
+        cache.get_rate_info("EUR", "GBP").expect("Error getting rate info"),
+        (providers::mock::ID.to_string(), 9710201602)
+    );
+
+    // Real `pair` responses served locally, one connection per request
+    use providers::Provider;
+    let serve = |body: &'static str| {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("Error binding");
+        let endpoint = format!("http://{}/", listener.local_addr().expect("Error"));
+        let server = std::thread::spawn(move || {
+            use std::io::{BufRead, Write};
+            let (stream, _) = listener.accept().expect("Error accepting");
+            let mut reader = std::io::BufReader::new(stream);
+            let mut request = String::new();
+            reader.read_line(&mut request).expect("Error reading request");
+            let mut line = String::new();
+            while reader.read_line(&mut line).expect("Error reading headers") > 2 {
+                line.clear();
+            }
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .expect("Error writing response");
+            request
+        });
+        (providers::exchangerate_api::ExchangeRateApi::new(endpoint, "key".to_string()), server)
+    };
+    let (api, server) = serve(include_str!(concat!(
+        ".",
+        crate::main_separator!(),
+        "mock_data",
+        crate::main_separator!(),
+        "pair.json"
+    )));
+    let rates = api.get_pair("eur", "gbp").expect("Error getting pair").expect("No pair");
+    assert!(server.join().expect("Server panicked").starts_with("GET /key/pair/EUR/GBP "));
+    assert_eq!(rates.base_code, "EUR");
+    assert_eq!(rates.time_next_update_unix, 1585353700);
+    assert_eq!(rates.conversion_rates, [("GBP".to_string(), Decimal::new(8412, 4))].into());
+    let (api, server) = serve(include_str!(concat!(
+        ".",
+        crate::main_separator!(),
+        "mock_data",
+        crate::main_separator!(),
+        "pair_amount.json"
+    )));
+    let conversion = api.convert("EUR", "GBP", "7").expect("Error converting on provider side");
+    assert!(server.join().expect("Server panicked").starts_with("GET /key/pair/EUR/GBP/7 "));
+    assert_eq!(conversion.conversion_rate.to_string(), "0.8412");
+    assert_eq!(conversion.conversion_result, Some(Decimal::new(58884, 4)));
+}